@@ -21,6 +21,12 @@ use tiktoken_rs::o200k_base;
 
 use crate::proto::ProtoCli;
 
+/// Reserve held back from `max_context_tokens` for the next completion.
+const TOKEN_BUFFER: usize = 500;
+/// Number of most-recent conversation messages that are never summarized away,
+/// so tool-call/tool-result pairs are never split across the compaction boundary.
+const KEEP_LAST_MESSAGES: usize = 6;
+
 /// Codex CLI
 ///
 /// If no subcommand is specified, options will be forwarded to the interactive CLI.
@@ -82,6 +88,19 @@ enum Subcommand {
     /// Internal: generate TypeScript protocol bindings.
     #[clap(hide = true)]
     GenerateTs(GenerateTsCommand),
+
+    /// Re-check a recorded autonomous session's tool-policy routing,
+    /// compaction triggers, and context rendering against the current
+    /// config, without making any network calls.
+    Replay(ReplayCommand),
+
+    /// Deterministically replay recorded approval-hook/tool-policy
+    /// decisions against a fixture file, without calling a live LLM.
+    ApprovalReplay(ApprovalReplayCommand),
+
+    /// Run one or more workload files through the supervisor loop and report
+    /// wall-clock time, LLM round trips, and tool-call counts.
+    Bench(BenchCommand),
 }
 
 #[derive(Debug, Parser)]
@@ -142,18 +161,37 @@ struct GenerateTsCommand {
 }
 
 #[derive(Debug, Parser)]
+// If `manage` is given, ignore requirements of the run-mode args (mirrors
+// `MultitoolCli`'s subcommand_negates_reqs handling of its own flat args).
+#[clap(subcommand_negates_reqs = true)]
 struct AutonomousCommand {
+    /// Inspect or control already-running autonomous sessions instead of
+    /// starting a new one.
+    #[clap(subcommand)]
+    manage: Option<AutonomousManageCommand>,
+
     /// Path to the configuration YAML file.
     #[clap(long, short = 'f', value_name = "FILE")]
     config_file: PathBuf,
 
-    /// Duration to run in autonomous mode (in minutes).
-    #[clap(long, short = 'd', default_value = "30")]
-    duration: u64,
-
-    /// Model to use for the external LLM driver.
-    #[clap(long, short = 'm', default_value = "o3")]
-    driver_model: String,
+    /// Named profile to load from the config file's `profiles` map (bundles
+    /// driver model, specialist mode, work-hours window, approval/sandbox
+    /// policy, and max-context-tokens). CLI flags below override whatever
+    /// the profile sets.
+    #[clap(long, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// Duration to run in autonomous mode (in minutes). Overrides the
+    /// profile; falls back to 30 if neither is set.
+    #[clap(long, short = 'd')]
+    duration: Option<u64>,
+
+    /// Model to use for the external LLM driver. Overrides the profile;
+    /// falls back to "o3" if neither is set. Prefix with a name from the
+    /// config file's `providers` section (e.g. "anthropic:claude-3-opus")
+    /// to pick that backend explicitly, or rely on its `model_prefixes`.
+    #[clap(long, short = 'm')]
+    driver_model: Option<String>,
 
     /// Enable full-auto mode (skip all approvals and use workspace-write sandbox).
     #[clap(long = "full-auto")]
@@ -163,13 +201,15 @@ struct AutonomousCommand {
     #[clap(long, value_name = "DIR")]
     resume_dir: Option<PathBuf>,
 
-    /// Start hour for active operation (0-23, Pacific time).
-    #[clap(long, default_value = "0")]
-    work_start_hour: u8,
+    /// Start hour for active operation (0-23, Pacific time). Overrides the
+    /// profile; falls back to 0 if neither is set.
+    #[clap(long)]
+    work_start_hour: Option<u8>,
 
-    /// End hour for active operation (0-23, Pacific time).
-    #[clap(long, default_value = "23")]
-    work_end_hour: u8,
+    /// End hour for active operation (0-23, Pacific time). Overrides the
+    /// profile; falls back to 23 if neither is set.
+    #[clap(long)]
+    work_end_hour: Option<u8>,
     /// Ignore Pacific time work-hour pauses and run continuously.
     #[clap(long)]
     ignore_work_hours: bool,
@@ -178,14 +218,181 @@ struct AutonomousCommand {
     #[clap(long, value_name = "DIR")]
     logs_dir: Option<PathBuf>,
 
-    /// Mode/specialist to use for the codex instance.
+    /// Mode/specialist to use for the codex instance. Overrides the profile.
     #[clap(long, value_name = "MODE")]
     mode: Option<String>,
 
+    /// How to gate side-effecting ("may_"-style) tool calls before they run:
+    /// `auto` (run everything), `readonly` (auto-run read tools, deny
+    /// side-effecting ones), or `interactive` (prompt the operator on stdin
+    /// for side-effecting ones). Overrides the profile; falls back to
+    /// "auto" if neither is set.
+    #[clap(long, value_name = "MODE")]
+    tool_confirmation_mode: Option<String>,
+
+    /// Maximum number of tokens to keep in the driver context before older
+    /// conversation history is summarized and spliced back in as a single
+    /// synthetic system message. Overrides the profile; falls back to
+    /// 200000 if neither is set.
+    #[clap(long)]
+    max_context_tokens: Option<usize>,
+
+    /// Serve a live observability/control tunnel on this address (e.g.
+    /// `127.0.0.1:7878`): remote clients can watch the heartbeat and
+    /// conversation log over a WebSocket, send steering messages that get
+    /// injected into the next iteration's prompt, pause/resume the loop
+    /// between iterations, remotely approve or deny a pending approval
+    /// request, adjust `max_context_tokens`, or request an immediate
+    /// checkpoint-and-exit.
+    #[clap(long, value_name = "ADDR")]
+    serve: Option<String>,
+
+    /// Shared-secret token required of `--serve` clients (as a `?token=`
+    /// query parameter on the WebSocket upgrade, or an `Authorization:
+    /// Bearer <token>` header on the plain HTTP endpoints). With no token
+    /// set, the tunnel accepts any client, matching prior behavior.
+    #[clap(long, value_name = "TOKEN")]
+    serve_token: Option<String>,
+
+    /// How long to hold an `ExecApprovalRequest`/`ApplyPatchApprovalRequest`
+    /// open for a human to approve/deny it over the `--serve` control
+    /// channel before falling back to the external LLM's decision.
+    #[clap(long, default_value_t = 30)]
+    remote_approval_timeout_secs: u64,
+
+    /// Watch this directory for new task files (`*.yaml`/`*.json`) instead
+    /// of looping on a fixed duration: each file that lands is dequeued,
+    /// seeds a fresh iteration's prompt, and is archived into the session
+    /// logs directory once processed.
+    #[clap(long, value_name = "DIR")]
+    watch: Option<PathBuf>,
+
     #[clap(flatten)]
     config_overrides: CliConfigOverrides,
 }
 
+#[derive(Debug, clap::Subcommand)]
+enum AutonomousManageCommand {
+    /// List known autonomous sessions discovered under `./logs` and the
+    /// backup logs directory, along with their liveness.
+    List {
+        /// A heartbeat is considered stale if `last_updated` is older than
+        /// this many seconds.
+        #[clap(long, default_value = "120")]
+        stale_after_secs: u64,
+    },
+
+    /// Show detailed status for a single session.
+    Status {
+        /// Session name, e.g. `autonomous_session_1700000000`.
+        session: String,
+
+        #[clap(long, default_value = "120")]
+        stale_after_secs: u64,
+    },
+
+    /// Terminate the recorded PID for a session.
+    Stop {
+        /// Session name, e.g. `autonomous_session_1700000000`.
+        session: String,
+    },
+
+    /// Relaunch a session's autonomous loop with `--resume-dir` pointed at
+    /// its existing logs directory, picking up from `latest.json` and
+    /// `context_log.txt`. Re-supplies `--driver-model`, `--full-auto`,
+    /// `--profile`, `--tool-confirmation-mode`, `--max-context-tokens`, and
+    /// `--serve` from the session's recorded heartbeat so the restarted
+    /// session doesn't silently fall back to weaker defaults (e.g. losing
+    /// interactive tool confirmation). `--serve-token` is never recorded to
+    /// disk, so it's never re-supplied automatically; pass it again
+    /// explicitly if the original session had one.
+    Restart {
+        /// Session name, e.g. `autonomous_session_1700000000`.
+        session: String,
+
+        /// Duration to run in autonomous mode (in minutes).
+        #[clap(long, default_value = "30")]
+        duration: u64,
+
+        /// `--serve-token` to pass to the relaunched session. Never
+        /// recorded in `heartbeat.json`, so it has to be re-supplied here
+        /// if the original session was serving with one.
+        #[clap(long, value_name = "TOKEN")]
+        serve_token: Option<String>,
+    },
+}
+
+#[derive(Debug, Parser)]
+struct ReplayCommand {
+    /// Directory of a previously-run autonomous session, e.g.
+    /// `./logs/autonomous_session_1700000000`.
+    #[clap(long, short = 'd', value_name = "DIR")]
+    session_dir: PathBuf,
+
+    /// Path to the configuration YAML file whose templates and tool
+    /// policies should be replayed against the recorded session. Defaults
+    /// to the `config_file` recorded in the session's `heartbeat.json`.
+    #[clap(long, short = 'f', value_name = "FILE")]
+    config_file: Option<PathBuf>,
+
+    /// Write (or overwrite) `replay_snapshot.json` in the session directory
+    /// with the freshly computed report and exit without reporting
+    /// regressions. Without this flag, an existing snapshot is only compared
+    /// for its iteration count.
+    #[clap(long)]
+    update_snapshot: bool,
+}
+
+#[derive(Debug, Parser)]
+struct ApprovalReplayCommand {
+    /// JSON fixture file of recorded approval scenarios, e.g. produced by
+    /// hand or captured from a live session (see `ApprovalFixtureScenario`).
+    #[clap(long, short = 'f', value_name = "FILE")]
+    fixtures_file: PathBuf,
+
+    /// Path to the configuration YAML file whose `tool_policies` and
+    /// `approval_hooks` each scenario is replayed against.
+    #[clap(long, short = 'c', value_name = "FILE")]
+    config_file: PathBuf,
+
+    /// Re-run the whole suite whenever the fixture or config file changes on
+    /// disk instead of exiting after one pass.
+    #[clap(long)]
+    watch: bool,
+}
+
+#[derive(Debug, Parser)]
+struct BenchCommand {
+    /// One or more JSON workload files, each describing a supervisor session
+    /// to benchmark (see `BenchWorkload`: `name`, `target`/`asset`,
+    /// `user_message`, `max_steps`, `env`).
+    #[clap(required = true)]
+    workloads: Vec<PathBuf>,
+
+    /// Path to the configuration YAML file (tool policies, plugins, hooks)
+    /// every workload is run against.
+    #[clap(long, short = 'f', value_name = "FILE")]
+    config_file: PathBuf,
+
+    /// Driver model to benchmark. Falls back to "o3" like `autonomous` does.
+    #[clap(long, short = 'm')]
+    driver_model: Option<String>,
+
+    /// Write the aggregate JSON report here instead of stdout.
+    #[clap(long, short = 'o', value_name = "FILE")]
+    report_out: Option<PathBuf>,
+
+    /// POST the report to this dashboard URL for regression tracking.
+    #[clap(long, value_name = "URL")]
+    dashboard_url: Option<String>,
+
+    /// API key sent as `Authorization: Bearer <key>` when posting to
+    /// `--dashboard-url`. Falls back to the `BENCH_DASHBOARD_API_KEY` env
+    /// var.
+    #[clap(long, value_name = "KEY")]
+    dashboard_api_key: Option<String>,
+}
+
 fn main() -> anyhow::Result<()> {
     arg0_dispatch_or_else(|codex_linux_sandbox_exe| async move {
         cli_main(codex_linux_sandbox_exe).await?;
@@ -212,10 +419,13 @@ async fn cli_main(codex_linux_sandbox_exe: Option<PathBuf>) -> anyhow::Result<()
         Some(Subcommand::Mcp) => {
             codex_mcp_server::run_main(codex_linux_sandbox_exe, cli.config_overrides).await?;
         }
-        Some(Subcommand::Autonomous(mut autonomous_cli)) => {
-            prepend_config_flags(&mut autonomous_cli.config_overrides, cli.config_overrides);
-            run_autonomous_mode(autonomous_cli, codex_linux_sandbox_exe).await?;
-        }
+        Some(Subcommand::Autonomous(mut autonomous_cli)) => match autonomous_cli.manage.take() {
+            Some(manage_cli) => run_autonomous_manage(manage_cli).await?,
+            None => {
+                prepend_config_flags(&mut autonomous_cli.config_overrides, cli.config_overrides);
+                run_autonomous_mode(autonomous_cli, codex_linux_sandbox_exe).await?;
+            }
+        },
         Some(Subcommand::Login(mut login_cli)) => {
             prepend_config_flags(&mut login_cli.config_overrides, cli.config_overrides);
             match login_cli.action {
@@ -267,211 +477,1687 @@ async fn cli_main(codex_linux_sandbox_exe: Option<PathBuf>) -> anyhow::Result<()
         Some(Subcommand::GenerateTs(gen_cli)) => {
             codex_protocol_ts::generate_ts(&gen_cli.out_dir, gen_cli.prettier.as_deref())?;
         }
+        Some(Subcommand::Replay(replay_cli)) => {
+            run_replay(replay_cli).await?;
+        }
+        Some(Subcommand::ApprovalReplay(approval_replay_cli)) => {
+            run_approval_replay(approval_replay_cli).await?;
+        }
+        Some(Subcommand::Bench(bench_cli)) => {
+            run_bench(bench_cli).await?;
+        }
     }
 
     Ok(())
 }
 
-async fn run_autonomous_mode(
-    autonomous_cli: AutonomousCommand,
-    _codex_linux_sandbox_exe: Option<PathBuf>,
-) -> anyhow::Result<()> {
-    use codex_core::ConversationManager;
-    use codex_core::config::Config;
-    use codex_core::protocol::InputItem;
-    use codex_core::protocol::Op;
-    use codex_login::AuthManager;
-    use std::sync::Arc;
-    use std::time::Duration;
-    use std::time::Instant;
-    use tokio::time::sleep;
+/// Shared state behind `--serve`: broadcasts checkpoint-shaped events to
+/// connected observers and collects inbound steering/control commands for
+/// the main loop to pick up between iterations and at tool-approval points.
+#[derive(Clone)]
+struct ObservabilityHub {
+    events: tokio::sync::broadcast::Sender<serde_json::Value>,
+    latest_heartbeat: std::sync::Arc<std::sync::Mutex<serde_json::Value>>,
+    latest_conversation: std::sync::Arc<std::sync::Mutex<Vec<serde_json::Value>>>,
+    steering_inbox: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+    paused: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    exit_requested: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    max_tokens_override: std::sync::Arc<std::sync::Mutex<Option<usize>>>,
+    /// Decisions an operator submitted remotely for a pending approval,
+    /// keyed by the same call/approval id the loop already tags
+    /// `ExecApprovalRequest`/tool-approval events with. `true` = approved.
+    remote_decisions: std::sync::Arc<std::sync::Mutex<std::collections::HashMap<String, bool>>>,
+    /// Shared secret required of `--serve` clients; `None` means the tunnel
+    /// is unauthenticated, matching the tunnel's original behavior.
+    serve_token: Option<String>,
+}
 
-    println!("🚀 Starting autonomous mode...");
-    println!("📁 Config file: {:?}", autonomous_cli.config_file);
-    if let Some(ref resume_dir) = autonomous_cli.resume_dir {
-        println!("🔄 Resuming from: {:?}", resume_dir);
+impl ObservabilityHub {
+    fn new(serve_token: Option<String>) -> Self {
+        let (events, _) = tokio::sync::broadcast::channel(256);
+        Self {
+            events,
+            latest_heartbeat: std::sync::Arc::new(std::sync::Mutex::new(serde_json::json!({}))),
+            latest_conversation: std::sync::Arc::new(std::sync::Mutex::new(Vec::new())),
+            steering_inbox: std::sync::Arc::new(std::sync::Mutex::new(Vec::new())),
+            paused: std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            exit_requested: std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            max_tokens_override: std::sync::Arc::new(std::sync::Mutex::new(None)),
+            remote_decisions: std::sync::Arc::new(std::sync::Mutex::new(
+                std::collections::HashMap::new(),
+            )),
+            serve_token,
+        }
     }
-    println!("⏰ Duration: {} minutes", autonomous_cli.duration);
-    println!("🤖 Driver model: {}", autonomous_cli.driver_model);
-
-    // Load config file
-    let config_content =
-        std::fs::read_to_string(&autonomous_cli.config_file).with_context(|| {
-            format!(
-                "Failed to read config file: {:?}",
-                autonomous_cli.config_file
-            )
-        })?;
-
-    // Load prompt templates from core directory
-    let core_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-        .parent()
-        .unwrap()
-        .join("core");
 
-    let initial_prompt_file = core_dir.join("initial_prompt.txt");
-    let continuation_prompt_file = core_dir.join("continuation_prompt.txt");
-    let approval_prompt_file = core_dir.join("approval_prompt.txt");
-    let bugcrowd_approval_prompt_file = core_dir.join("bugcrowd_approval_prompt.txt");
-    let summarization_prompt_file = core_dir.join("summarization_prompt.txt");
-
-    let initial_prompt_template =
-        std::fs::read_to_string(&initial_prompt_file).with_context(|| {
-            format!(
-                "Failed to read initial prompt file: {:?}",
-                initial_prompt_file
-            )
-        })?;
+    fn is_authorized(&self, supplied: Option<&str>) -> bool {
+        match &self.serve_token {
+            None => true,
+            Some(expected) => supplied == Some(expected.as_str()),
+        }
+    }
 
-    let continuation_prompt_template = std::fs::read_to_string(&continuation_prompt_file)
-        .with_context(|| {
-            format!(
-                "Failed to read continuation prompt file: {:?}",
-                continuation_prompt_file
-            )
-        })?;
+    /// Called from `save_checkpoint` on the same cadence as the on-disk
+    /// logs, so the live feed and the checkpoint files never diverge.
+    fn publish_checkpoint(&self, heartbeat: &serde_json::Value, conversation_log: &[serde_json::Value]) {
+        *self.latest_heartbeat.lock().unwrap() = heartbeat.clone();
+        *self.latest_conversation.lock().unwrap() = conversation_log.to_vec();
+        let _ = self.events.send(serde_json::json!({
+            "type": "checkpoint",
+            "heartbeat": heartbeat,
+            "conversation_log": conversation_log,
+        }));
+    }
 
-    let approval_prompt_template =
-        std::fs::read_to_string(&approval_prompt_file).with_context(|| {
-            format!(
-                "Failed to read approval prompt file: {:?}",
-                approval_prompt_file
-            )
-        })?;
+    /// Drain and return any steering messages an operator sent since the
+    /// last iteration.
+    fn drain_steering_messages(&self) -> Vec<String> {
+        std::mem::take(&mut self.steering_inbox.lock().unwrap())
+    }
 
-    let bugcrowd_approval_prompt_template = std::fs::read_to_string(&bugcrowd_approval_prompt_file)
-        .with_context(|| {
-            format!(
-                "Failed to read bugcrowd approval prompt file: {:?}",
-                bugcrowd_approval_prompt_file
-            )
-        })?;
+    fn is_paused(&self) -> bool {
+        self.paused.load(std::sync::atomic::Ordering::SeqCst)
+    }
 
-    let summarization_prompt_template = std::fs::read_to_string(&summarization_prompt_file)
-        .with_context(|| {
-            format!(
-                "Failed to read summarization prompt file: {:?}",
-                summarization_prompt_file
-            )
-        })?;
+    fn should_exit(&self) -> bool {
+        self.exit_requested.load(std::sync::atomic::Ordering::SeqCst)
+    }
 
-    println!("📋 Task config loaded");
-    println!("📝 Prompt templates loaded");
+    /// Consume and return the most recent operator-submitted `max_tokens`
+    /// override, if any, so each iteration only applies it once.
+    fn take_max_tokens_override(&self) -> Option<usize> {
+        self.max_tokens_override.lock().unwrap().take()
+    }
 
-    // Create codex config with overrides, applying full-auto settings if enabled
-    let mut config_overrides = codex_core::config::ConfigOverrides::default();
-    if autonomous_cli.full_auto {
-        config_overrides.approval_policy = Some(codex_core::protocol::AskForApproval::OnFailure);
-        config_overrides.sandbox_mode =
-            Some(codex_protocol::config_types::SandboxMode::WorkspaceWrite);
+    /// Consume and return an operator's remote approve/deny decision for
+    /// `id`, if one was submitted, so the loop can skip consulting the
+    /// external LLM for this call.
+    fn take_remote_decision(&self, id: &str) -> Option<bool> {
+        self.remote_decisions.lock().unwrap().remove(id)
     }
 
-    // Set specialist mode if provided
-    if let Some(mode) = autonomous_cli.mode {
-        config_overrides.specialist = Some(mode);
+    /// Broadcast a pending approval to any connected `--serve` client so an
+    /// operator can react before the external LLM is consulted. `kind` is
+    /// `"exec"`, `"patch"`, `"mcp_tool"`, or `"plugin_tool"`.
+    fn publish_approval_request(&self, kind: &str, id: &str, prompt: &str) {
+        let _ = self.events.send(serde_json::json!({
+            "type": "approval_request",
+            "kind": kind,
+            "id": id,
+            "prompt": prompt,
+        }));
     }
 
-    let config = Config::load_with_cli_overrides(
-        autonomous_cli
-            .config_overrides
-            .parse_overrides()
-            .map_err(anyhow::Error::msg)?,
-        config_overrides,
-    )
-    .with_context(|| "Failed to load codex config")?;
+    /// Poll for an operator's remote decision on `id` until one arrives or
+    /// `timeout` elapses, whichever comes first.
+    async fn await_remote_decision(&self, id: &str, timeout: std::time::Duration) -> Option<bool> {
+        const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+        let deadline = std::time::Instant::now() + timeout;
+        loop {
+            if let Some(decision) = self.take_remote_decision(id) {
+                return Some(decision);
+            }
+            if std::time::Instant::now() >= deadline {
+                return None;
+            }
+            tokio::time::sleep(POLL_INTERVAL.min(deadline - std::time::Instant::now())).await;
+        }
+    }
 
-    // Debug: Log the actual config being used
-    println!(
-        "🔧 DEBUG: Loaded config - model: {}, provider: {}",
-        config.model, config.model_provider.name
-    );
-    println!("🔧 DEBUG: Driver model: {}", autonomous_cli.driver_model);
-    println!(
-        "🔧 DEBUG: OPENROUTER_API_KEY: {}",
-        if std::env::var("OPENROUTER_API_KEY").is_ok() {
-            "SET"
-        } else {
-            "NOT SET"
+    /// Apply a single parsed control command, mutating the hub's shared
+    /// state the same way regardless of whether it arrived over the
+    /// WebSocket or the `/control` HTTP endpoint.
+    fn apply_command(&self, command: &serde_json::Value) {
+        match command.get("type").and_then(|t| t.as_str()) {
+            Some("steer") => {
+                if let Some(message) = command.get("message").and_then(|m| m.as_str()) {
+                    self.steering_inbox.lock().unwrap().push(message.to_string());
+                }
+            }
+            Some("pause") => {
+                self.paused.store(true, std::sync::atomic::Ordering::SeqCst);
+                println!("🛰️  Operator requested pause");
+            }
+            Some("resume") => {
+                self.paused.store(false, std::sync::atomic::Ordering::SeqCst);
+                println!("🛰️  Operator requested resume");
+            }
+            Some("approve") | Some("deny") => {
+                if let Some(id) = command.get("id").and_then(|i| i.as_str()) {
+                    let approved = command.get("type").and_then(|t| t.as_str()) == Some("approve");
+                    println!(
+                        "🛰️  Operator {} pending request '{}'",
+                        if approved { "approved" } else { "denied" },
+                        id
+                    );
+                    self.remote_decisions
+                        .lock()
+                        .unwrap()
+                        .insert(id.to_string(), approved);
+                }
+            }
+            Some("set_max_tokens") => {
+                if let Some(value) = command.get("value").and_then(|v| v.as_u64()) {
+                    println!("🛰️  Operator set max_context_tokens override to {}", value);
+                    *self.max_tokens_override.lock().unwrap() = Some(value as usize);
+                }
+            }
+            Some("checkpoint_and_exit") => {
+                println!("🛰️  Operator requested checkpoint-and-exit");
+                self.exit_requested
+                    .store(true, std::sync::atomic::Ordering::SeqCst);
+            }
+            _ => {
+                // Not a recognized command envelope: ignored here. The
+                // WebSocket handler only passes recognized commands to this
+                // function and queues any other frame as a plain steering
+                // message, preserving the original `--serve` behavior for
+                // clients that just send text.
+            }
         }
-    );
-    println!(
-        "🔧 DEBUG: OPENAI_API_KEY: {}",
-        if std::env::var("OPENAI_API_KEY").is_ok() {
-            "SET"
-        } else {
-            "NOT SET"
+    }
+}
+
+/// Start the `--serve` HTTP+WebSocket tunnel in the background. Clients can
+/// `GET /heartbeat` / `GET /conversation` for a snapshot, upgrade `/stream`
+/// to a WebSocket to receive every published checkpoint event and push back
+/// control commands, or `POST /control` with the same command JSON for a
+/// one-shot client that doesn't want to hold a WebSocket open. A command is
+/// a JSON object `{"type": "steer"|"pause"|"resume"|"approve"|"deny"|
+/// "set_max_tokens"|"checkpoint_and_exit", ...}`; any inbound WebSocket text
+/// frame that isn't one of these is queued as a steering message verbatim,
+/// preserving the original plain-text steering behavior.
+fn spawn_observability_server(addr: String, hub: ObservabilityHub) {
+    tokio::spawn(async move {
+        use axum::extract::State;
+        use axum::extract::ws::Message;
+        use axum::extract::ws::WebSocket;
+        use axum::extract::ws::WebSocketUpgrade;
+        use axum::response::IntoResponse;
+        use axum::response::Json;
+        use axum::routing::get;
+        use axum::routing::post;
+        use futures::SinkExt;
+        use futures::StreamExt;
+
+        // Pull a bearer token out of `Authorization: Bearer <token>`, for the
+        // plain HTTP endpoints.
+        fn bearer_token(headers: &axum::http::HeaderMap) -> Option<String> {
+            headers
+                .get(axum::http::header::AUTHORIZATION)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.strip_prefix("Bearer "))
+                .map(|v| v.to_string())
         }
-    );
 
-    // Initialize codex session
-    let codex_home = codex_core::config::find_codex_home()?;
-    let auth_manager = Arc::new(AuthManager::new(codex_home, codex_login::AuthMode::ChatGPT));
-    let conversation_manager = ConversationManager::new(auth_manager);
-    let new_conversation = conversation_manager
-        .new_conversation(config.clone())
-        .await?;
-    let codex = new_conversation.conversation;
-    println!("✅ Codex session initialized");
+        #[derive(serde::Deserialize)]
+        struct WsAuthQuery {
+            token: Option<String>,
+        }
 
-    // Initialize context accumulator and conversation log
-    let mut context = String::new();
-    let mut conversation_log = Vec::new();
-    let mut iteration = 0;
+        async fn get_heartbeat(
+            State(hub): State<ObservabilityHub>,
+            headers: axum::http::HeaderMap,
+        ) -> Result<Json<serde_json::Value>, axum::http::StatusCode> {
+            if !hub.is_authorized(bearer_token(&headers).as_deref()) {
+                return Err(axum::http::StatusCode::UNAUTHORIZED);
+            }
+            Ok(Json(hub.latest_heartbeat.lock().unwrap().clone()))
+        }
 
-    // Load resume context if resume directory is provided
-    if let Some(ref resume_dir) = autonomous_cli.resume_dir {
-        println!("🔄 Loading resume context from {:?}", resume_dir);
+        async fn get_conversation(
+            State(hub): State<ObservabilityHub>,
+            headers: axum::http::HeaderMap,
+        ) -> Result<Json<Vec<serde_json::Value>>, axum::http::StatusCode> {
+            if !hub.is_authorized(bearer_token(&headers).as_deref()) {
+                return Err(axum::http::StatusCode::UNAUTHORIZED);
+            }
+            Ok(Json(hub.latest_conversation.lock().unwrap().clone()))
+        }
 
-        // Load context from context_log.txt
-        let context_log_file = resume_dir.join("context_log.txt");
-        if context_log_file.exists() {
-            context = std::fs::read_to_string(&context_log_file)
-                .with_context(|| format!("Failed to read context log: {:?}", context_log_file))?;
-            println!("✅ Context log loaded ({} bytes)", context.len());
+        async fn post_control(
+            State(hub): State<ObservabilityHub>,
+            headers: axum::http::HeaderMap,
+            Json(command): Json<serde_json::Value>,
+        ) -> Result<Json<serde_json::Value>, axum::http::StatusCode> {
+            if !hub.is_authorized(bearer_token(&headers).as_deref()) {
+                return Err(axum::http::StatusCode::UNAUTHORIZED);
+            }
+            hub.apply_command(&command);
+            Ok(Json(serde_json::json!({ "ok": true })))
         }
 
-        // Load conversation from latest.json
-        let latest_file = resume_dir.join("latest.json");
-        if latest_file.exists() {
-            let latest_content = std::fs::read_to_string(&latest_file)
-                .with_context(|| format!("Failed to read latest.json: {:?}", latest_file))?;
-            conversation_log = serde_json::from_str(&latest_content)
-                .with_context(|| format!("Failed to parse latest.json: {:?}", latest_file))?;
-            println!(
-                "✅ Conversation log loaded ({} messages)",
-                conversation_log.len()
-            );
+        async fn ws_handler(
+            ws: WebSocketUpgrade,
+            State(hub): State<ObservabilityHub>,
+            axum::extract::Query(query): axum::extract::Query<WsAuthQuery>,
+        ) -> axum::response::Response {
+            if !hub.is_authorized(query.token.as_deref()) {
+                return axum::http::StatusCode::UNAUTHORIZED.into_response();
+            }
+            ws.on_upgrade(move |socket| handle_socket(socket, hub))
         }
 
-        // Determine next iteration number from existing files
-        let mut max_iteration = 0;
-        if let Ok(entries) = std::fs::read_dir(resume_dir) {
-            for entry in entries {
-                if let Ok(entry) = entry {
-                    let filename = entry.file_name().to_string_lossy().to_string();
-                    if filename.starts_with("iteration_") && filename.ends_with(".json") {
-                        if let Ok(iter_num) = filename[10..13].parse::<u32>() {
-                            max_iteration = max_iteration.max(iter_num);
+        async fn handle_socket(socket: WebSocket, hub: ObservabilityHub) {
+            let (mut sender, mut receiver) = socket.split();
+            let mut events = hub.events.subscribe();
+
+            let send_task = tokio::spawn(async move {
+                while let Ok(event) = events.recv().await {
+                    if sender
+                        .send(Message::Text(event.to_string().into()))
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+
+            while let Some(Ok(msg)) = receiver.next().await {
+                if let Message::Text(text) = msg {
+                    match serde_json::from_str::<serde_json::Value>(&text) {
+                        Ok(command)
+                            if matches!(
+                                command.get("type").and_then(|t| t.as_str()),
+                                Some(
+                                    "steer" | "pause" | "resume" | "approve" | "deny"
+                                        | "set_max_tokens" | "checkpoint_and_exit"
+                                )
+                            ) =>
+                        {
+                            hub.apply_command(&command);
+                        }
+                        _ => {
+                            hub.steering_inbox.lock().unwrap().push(text.to_string());
                         }
                     }
                 }
             }
-        }
-        iteration = max_iteration + 1;
-        println!("✅ Resuming from iteration {}", iteration);
-    }
-    let start_time = Instant::now();
-    let _duration = Duration::from_secs(autonomous_cli.duration * 60);
 
-    // Create or use existing session-specific logs directory
-    let session_timestamp = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
+            send_task.abort();
+        }
 
-    let session_logs_dir = if let Some(ref resume_dir) = autonomous_cli.resume_dir {
-        // Use existing directory for resume
-        resume_dir.clone()
+        let app = axum::Router::new()
+            .route("/heartbeat", get(get_heartbeat))
+            .route("/conversation", get(get_conversation))
+            .route("/control", post(post_control))
+            .route("/stream", get(ws_handler))
+            .with_state(hub);
+
+        match tokio::net::TcpListener::bind(&addr).await {
+            Ok(listener) => {
+                println!("🛰️  Observability tunnel listening on {}", addr);
+                if let Err(e) = axum::serve(listener, app).await {
+                    eprintln!("❌ Observability tunnel exited: {}", e);
+                }
+            }
+            Err(e) => {
+                eprintln!("❌ Failed to bind observability tunnel on {}: {}", addr, e);
+            }
+        }
+    });
+}
+
+/// Spawn a filesystem watcher over `dir` and forward newly created
+/// `*.yaml`/`*.yml`/`*.json` task files to the returned channel in arrival
+/// order, turning `--watch` into a long-lived worker fed by an external
+/// producer instead of a fixed `--duration`.
+fn spawn_task_watcher(dir: PathBuf) -> tokio::sync::mpsc::UnboundedReceiver<PathBuf> {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        use notify::Watcher;
+
+        let (watcher_tx, watcher_rx) = std::sync::mpsc::channel();
+        let mut watcher = match notify::recommended_watcher(watcher_tx) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                eprintln!("❌ Failed to create task watcher for {:?}: {}", dir, e);
+                return;
+            }
+        };
+        if let Err(e) = watcher.watch(&dir, notify::RecursiveMode::NonRecursive) {
+            eprintln!("❌ Failed to watch {:?}: {}", dir, e);
+            return;
+        }
+
+        for event in watcher_rx {
+            let Ok(event) = event else { continue };
+            if !matches!(event.kind, notify::EventKind::Create(_)) {
+                continue;
+            }
+            for path in event.paths {
+                let is_task_file = path
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .map(|ext| ext == "yaml" || ext == "yml" || ext == "json")
+                    .unwrap_or(false);
+                if is_task_file && tx.send(path).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    rx
+}
+
+/// Spawn a filesystem watcher over a fixed list of individual files and
+/// forward a notification on every modify event. Same thread+channel shape
+/// as [`spawn_task_watcher`], but watches specific files instead of
+/// scanning a directory for new arrivals.
+fn spawn_file_watcher(paths: Vec<PathBuf>) -> tokio::sync::mpsc::UnboundedReceiver<()> {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        use notify::Watcher;
+
+        let (watcher_tx, watcher_rx) = std::sync::mpsc::channel();
+        let mut watcher = match notify::recommended_watcher(watcher_tx) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                eprintln!("❌ Failed to create file watcher: {}", e);
+                return;
+            }
+        };
+        for path in &paths {
+            if let Err(e) = watcher.watch(path, notify::RecursiveMode::NonRecursive) {
+                eprintln!("❌ Failed to watch {:?}: {}", path, e);
+                return;
+            }
+        }
+
+        for event in watcher_rx {
+            let Ok(event) = event else { continue };
+            if matches!(event.kind, notify::EventKind::Modify(_)) && tx.send(()).is_err() {
+                return;
+            }
+        }
+    });
+    rx
+}
+
+/// A named entry under the config file's top-level `profiles` map. Every
+/// field is optional: whatever a profile doesn't set falls through to the
+/// hardcoded default for that setting (see `run_autonomous_mode`).
+#[derive(Debug, Default, serde::Deserialize)]
+struct AutonomousProfile {
+    driver_model: Option<String>,
+    duration: Option<u64>,
+    work_start_hour: Option<u8>,
+    work_end_hour: Option<u8>,
+    full_auto: Option<bool>,
+    approval_policy: Option<String>,
+    sandbox_mode: Option<String>,
+    mode: Option<String>,
+    max_context_tokens: Option<usize>,
+    tool_confirmation_mode: Option<String>,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct AutonomousProfilesFile {
+    #[serde(default)]
+    profiles: std::collections::HashMap<String, AutonomousProfile>,
+}
+
+/// Look up `name` in the config file's `profiles` map.
+fn load_autonomous_profile(config_content: &str, name: &str) -> anyhow::Result<AutonomousProfile> {
+    let profiles_file: AutonomousProfilesFile = serde_yaml::from_str(config_content)
+        .with_context(|| "Failed to parse `profiles` from config file")?;
+    profiles_file
+        .profiles
+        .get(name)
+        .map(|profile| AutonomousProfile {
+            driver_model: profile.driver_model.clone(),
+            duration: profile.duration,
+            work_start_hour: profile.work_start_hour,
+            work_end_hour: profile.work_end_hour,
+            full_auto: profile.full_auto,
+            approval_policy: profile.approval_policy.clone(),
+            sandbox_mode: profile.sandbox_mode.clone(),
+            mode: profile.mode.clone(),
+            max_context_tokens: profile.max_context_tokens,
+            tool_confirmation_mode: profile.tool_confirmation_mode.clone(),
+        })
+        .ok_or_else(|| anyhow::anyhow!("No profile named '{}' in config file", name))
+}
+
+fn parse_approval_policy(value: &str) -> anyhow::Result<codex_core::protocol::AskForApproval> {
+    match value {
+        "untrusted" | "unless-trusted" => Ok(codex_core::protocol::AskForApproval::UnlessTrusted),
+        "on-failure" => Ok(codex_core::protocol::AskForApproval::OnFailure),
+        "on-request" => Ok(codex_core::protocol::AskForApproval::OnRequest),
+        "never" => Ok(codex_core::protocol::AskForApproval::Never),
+        other => Err(anyhow::anyhow!(
+            "Unknown approval_policy '{}' in profile (expected one of: untrusted, on-failure, on-request, never)",
+            other
+        )),
+    }
+}
+
+fn parse_sandbox_mode(
+    value: &str,
+) -> anyhow::Result<codex_protocol::config_types::SandboxMode> {
+    match value {
+        "read-only" => Ok(codex_protocol::config_types::SandboxMode::ReadOnly),
+        "workspace-write" => Ok(codex_protocol::config_types::SandboxMode::WorkspaceWrite),
+        "danger-full-access" => Ok(codex_protocol::config_types::SandboxMode::DangerFullAccess),
+        other => Err(anyhow::anyhow!(
+            "Unknown sandbox_mode '{}' in profile (expected one of: read-only, workspace-write, danger-full-access)",
+            other
+        )),
+    }
+}
+
+/// What to do when the driver model (or codex itself) wants to invoke a
+/// tool or run a shell command, decided by [`resolve_tool_policy`] /
+/// [`resolve_command_tool_policy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ToolPolicyAction {
+    /// Let the call proceed without consulting the external LLM.
+    AutoApprove,
+    /// Route through the existing external-LLM approval prompt flow.
+    RequireLlmApproval,
+    /// Synthesize a denial without ever calling the tool or the LLM.
+    Deny,
+}
+
+fn parse_tool_policy_action(value: &str) -> anyhow::Result<ToolPolicyAction> {
+    match value {
+        "auto_approve" => Ok(ToolPolicyAction::AutoApprove),
+        "require_llm_approval" => Ok(ToolPolicyAction::RequireLlmApproval),
+        "deny" => Ok(ToolPolicyAction::Deny),
+        other => Err(anyhow::anyhow!(
+            "Unknown tool policy action '{}' (expected one of: auto_approve, require_llm_approval, deny)",
+            other
+        )),
+    }
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct ToolPoliciesFile {
+    #[serde(default)]
+    tool_policies: std::collections::HashMap<String, String>,
+}
+
+/// Load the optional `tool_policies` map from the config file: tool-name
+/// patterns (exact name, or a `prefix*` wildcard) to one of `auto_approve`,
+/// `require_llm_approval`, or `deny`. Tools with no matching pattern fall
+/// back to [`classify_tool_risk`].
+fn load_tool_policy_table(
+    config_content: &str,
+) -> anyhow::Result<std::collections::HashMap<String, ToolPolicyAction>> {
+    let parsed: ToolPoliciesFile = serde_yaml::from_str(config_content)
+        .with_context(|| "Failed to parse `tool_policies` from config file")?;
+
+    let mut table = std::collections::HashMap::new();
+    for (pattern, action) in parsed.tool_policies {
+        table.insert(pattern.clone(), parse_tool_policy_action(&action)?);
+    }
+    Ok(table)
+}
+
+/// Naming convention for tools that mutate state rather than just reading
+/// it: a name containing one of these markers anywhere (e.g. `write_note`,
+/// `bugcrowd_submit`, `slack_webhook`, `may_delete_file`) is treated as
+/// side-effecting.
+const MUTATING_TOOL_NAME_MARKERS: &[&str] = &["may_", "submit", "write", "webhook"];
+
+fn is_side_effecting_tool(tool_name: &str) -> bool {
+    MUTATING_TOOL_NAME_MARKERS
+        .iter()
+        .any(|marker| tool_name.contains(marker))
+}
+
+/// How `handle_supervisor_tool_calls` gates side-effecting calls (per
+/// [`is_side_effecting_tool`]'s `may_`-style naming convention, borrowed from
+/// aichat) before they run. Read-only tools like `read_notes` and the
+/// `finished` control tool always run regardless of mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ToolConfirmationMode {
+    /// Run every tool, side-effecting or not.
+    Auto,
+    /// Auto-run read-only tools; deny side-effecting ones without asking.
+    ReadOnly,
+    /// Auto-run read-only tools; prompt the operator on stdin, with the tool
+    /// name and pretty-printed arguments, for side-effecting ones.
+    Interactive,
+}
+
+fn parse_tool_confirmation_mode(value: &str) -> anyhow::Result<ToolConfirmationMode> {
+    match value {
+        "auto" => Ok(ToolConfirmationMode::Auto),
+        "readonly" | "read_only" | "read-only" => Ok(ToolConfirmationMode::ReadOnly),
+        "interactive" => Ok(ToolConfirmationMode::Interactive),
+        other => Err(anyhow::anyhow!(
+            "Unknown tool confirmation mode '{}' (expected one of: auto, readonly, interactive)",
+            other
+        )),
+    }
+}
+
+/// The inverse of [`parse_tool_confirmation_mode`], used to record the
+/// resolved mode in `heartbeat.json` so `AutonomousManageCommand::Restart`
+/// can pass it back on the command line it reconstructs.
+fn tool_confirmation_mode_as_flag_str(mode: ToolConfirmationMode) -> &'static str {
+    match mode {
+        ToolConfirmationMode::Auto => "auto",
+        ToolConfirmationMode::ReadOnly => "readonly",
+        ToolConfirmationMode::Interactive => "interactive",
+    }
+}
+
+/// Ask the operator on stdin whether to run a side-effecting tool call,
+/// pretty-printing its arguments so they can see exactly what would run
+/// before deciding. Run via `spawn_blocking` since it blocks on real stdin
+/// I/O and several calls may be prompting concurrently.
+fn prompt_operator_confirmation(tool_name: &str, arguments: &serde_json::Value) -> bool {
+    println!(
+        "⚠️  Supervisor wants to run side-effecting tool '{}' with arguments:\n{}",
+        tool_name,
+        serde_json::to_string_pretty(arguments).unwrap_or_else(|_| arguments.to_string())
+    );
+    print!("Allow? [y/N] ");
+    let _ = std::io::Write::flush(&mut std::io::stdout());
+    let mut input = String::new();
+    if std::io::stdin().read_line(&mut input).is_err() {
+        return false;
+    }
+    matches!(input.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Default policy for a tool with no explicit entry in the policy table:
+/// side-effecting tools require a look before they leap, read-only tools
+/// don't.
+fn classify_tool_risk(tool_name: &str) -> ToolPolicyAction {
+    if is_side_effecting_tool(tool_name) {
+        ToolPolicyAction::RequireLlmApproval
+    } else {
+        ToolPolicyAction::AutoApprove
+    }
+}
+
+/// Resolve the policy for an MCP/function tool call: an exact match in the
+/// table wins, then a `prefix*` wildcard match, then the naming-convention
+/// default from [`classify_tool_risk`].
+fn resolve_tool_policy(
+    policy_table: &std::collections::HashMap<String, ToolPolicyAction>,
+    tool_name: &str,
+) -> ToolPolicyAction {
+    if let Some(action) = policy_table.get(tool_name) {
+        return *action;
+    }
+    for (pattern, action) in policy_table {
+        if let Some(prefix) = pattern.strip_suffix('*') {
+            if tool_name.starts_with(prefix) {
+                return *action;
+            }
+        }
+    }
+    classify_tool_risk(tool_name)
+}
+
+/// Resolve the policy for a raw shell command under `ExecApprovalRequest`,
+/// where there's no single tool name to classify by naming convention.
+/// Patterns match as a substring of the joined command; with no match the
+/// existing always-ask-the-LLM behavior is preserved.
+fn resolve_command_tool_policy(
+    policy_table: &std::collections::HashMap<String, ToolPolicyAction>,
+    command: &[String],
+) -> ToolPolicyAction {
+    let command_str = command.join(" ");
+    for (pattern, action) in policy_table {
+        if command_str.contains(pattern.as_str()) {
+            return *action;
+        }
+    }
+    ToolPolicyAction::RequireLlmApproval
+}
+
+/// What an [`ApprovalHookConfig`] decides once its matcher has matched.
+/// `Defer` means the hook's matcher matched but it declines to rule, so
+/// evaluation continues on to the next hook instead of acting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ApprovalHookOutcome {
+    Allow,
+    Deny,
+    Llm,
+    Defer,
+}
+
+fn parse_approval_hook_action(value: &str) -> anyhow::Result<ApprovalHookOutcome> {
+    match value {
+        "allow" => Ok(ApprovalHookOutcome::Allow),
+        "deny" => Ok(ApprovalHookOutcome::Deny),
+        "llm" => Ok(ApprovalHookOutcome::Llm),
+        "defer" => Ok(ApprovalHookOutcome::Defer),
+        other => Err(anyhow::anyhow!(
+            "Unknown approval hook action '{}' (expected one of: allow, deny, llm, defer)",
+            other
+        )),
+    }
+}
+
+/// One entry in the config file's ordered `approval_hooks` list, matched
+/// against an `ExecApprovalRequest`/`ApplyPatchApprovalRequest` before
+/// falling back to the per-command `tool_policies` table (for exec) or
+/// straight to the external LLM (for patches). `command_contains` matches
+/// as a substring of the joined command (mirroring
+/// [`resolve_command_tool_policy`]); `cwd_prefix` as a path prefix;
+/// `changed_file_pattern` as an exact name or `prefix*` wildcard
+/// (mirroring [`resolve_tool_policy`]) against any changed file in a patch.
+/// A hook with none of these fields set matches everything, making it a
+/// suitable catch-all as the last entry in the list. A hook that sets only
+/// exec fields (`command_contains`/`cwd_prefix`) is exec-only and is never
+/// evaluated against a patch request, and symmetrically a hook that sets
+/// only `changed_file_pattern` is patch-only and never evaluated against an
+/// exec request.
+#[derive(Debug, Clone, serde::Deserialize)]
+struct ApprovalHookConfig {
+    name: String,
+    action: String,
+    #[serde(default)]
+    command_contains: Option<String>,
+    #[serde(default)]
+    cwd_prefix: Option<String>,
+    #[serde(default)]
+    changed_file_pattern: Option<String>,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct ApprovalHooksFile {
+    #[serde(default)]
+    approval_hooks: Vec<ApprovalHookConfig>,
+}
+
+/// Load the optional, ordered `approval_hooks` list from the config file.
+fn load_approval_hooks(config_content: &str) -> anyhow::Result<Vec<ApprovalHookConfig>> {
+    let parsed: ApprovalHooksFile = serde_yaml::from_str(config_content)
+        .with_context(|| "Failed to parse `approval_hooks` from config file")?;
+    Ok(parsed.approval_hooks)
+}
+
+fn matches_name_or_prefix_pattern(pattern: &str, value: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => value.starts_with(prefix),
+        None => value == pattern,
+    }
+}
+
+/// True if `hook` sets any field that only makes sense against an exec
+/// request, meaning it was authored for `evaluate_exec_approval_hooks` and
+/// should not also fire as a catch-all for patch requests.
+fn hook_has_exec_specific_fields(hook: &ApprovalHookConfig) -> bool {
+    hook.command_contains.is_some() || hook.cwd_prefix.is_some()
+}
+
+/// True if `hook` sets any field that only makes sense against a patch
+/// request, meaning it was authored for `evaluate_patch_approval_hooks` and
+/// should not also fire as a catch-all for exec requests.
+fn hook_has_patch_specific_fields(hook: &ApprovalHookConfig) -> bool {
+    hook.changed_file_pattern.is_some()
+}
+
+fn exec_approval_hook_matches(hook: &ApprovalHookConfig, command: &[String], cwd: &std::path::Path) -> bool {
+    if hook_has_patch_specific_fields(hook) && !hook_has_exec_specific_fields(hook) {
+        return false;
+    }
+    if let Some(pattern) = &hook.command_contains {
+        if !command.join(" ").contains(pattern.as_str()) {
+            return false;
+        }
+    }
+    if let Some(prefix) = &hook.cwd_prefix {
+        if !cwd.starts_with(prefix) {
+            return false;
+        }
+    }
+    true
+}
+
+fn patch_approval_hook_matches(
+    hook: &ApprovalHookConfig,
+    changes: &std::collections::HashMap<std::path::PathBuf, codex_core::protocol::FileChange>,
+) -> bool {
+    if hook_has_exec_specific_fields(hook) && !hook_has_patch_specific_fields(hook) {
+        return false;
+    }
+    if let Some(pattern) = &hook.changed_file_pattern {
+        return changes.keys().any(|path| {
+            matches_name_or_prefix_pattern(pattern, &path.to_string_lossy())
+        });
+    }
+    true
+}
+
+/// Evaluate `hooks` in order against an `ExecApprovalRequest`. Returns the
+/// name and outcome of the first hook whose matcher matches and whose
+/// action isn't `defer`; `None` means no hook decided, so the caller should
+/// fall back to its own default policy.
+fn evaluate_exec_approval_hooks(
+    hooks: &[ApprovalHookConfig],
+    command: &[String],
+    cwd: &std::path::Path,
+) -> anyhow::Result<Option<(String, ApprovalHookOutcome)>> {
+    for hook in hooks {
+        if !exec_approval_hook_matches(hook, command, cwd) {
+            continue;
+        }
+        let outcome = parse_approval_hook_action(&hook.action)?;
+        if outcome == ApprovalHookOutcome::Defer {
+            continue;
+        }
+        return Ok(Some((hook.name.clone(), outcome)));
+    }
+    Ok(None)
+}
+
+/// Evaluate `hooks` in order against an `ApplyPatchApprovalRequest`. Same
+/// semantics as [`evaluate_exec_approval_hooks`].
+fn evaluate_patch_approval_hooks(
+    hooks: &[ApprovalHookConfig],
+    changes: &std::collections::HashMap<std::path::PathBuf, codex_core::protocol::FileChange>,
+) -> anyhow::Result<Option<(String, ApprovalHookOutcome)>> {
+    for hook in hooks {
+        if !patch_approval_hook_matches(hook, changes) {
+            continue;
+        }
+        let outcome = parse_approval_hook_action(&hook.action)?;
+        if outcome == ApprovalHookOutcome::Defer {
+            continue;
+        }
+        return Ok(Some((hook.name.clone(), outcome)));
+    }
+    Ok(None)
+}
+
+/// A single incremental piece of codex output: rendered live to the
+/// terminal and appended to `stream.jsonl` in the session logs directory as
+/// it arrives, instead of only becoming visible once a whole message,
+/// reasoning block, or tool call has finished.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "type")]
+enum StreamChunk {
+    TextDelta { text: String },
+    ReasoningDelta { text: String },
+    ToolArgsDelta { call_id: String, partial_json: String },
+    ToolArgsComplete {
+        call_id: String,
+        name: String,
+        arguments: serde_json::Value,
+    },
+}
+
+/// Accumulates `ToolArgsDelta` fragments per `call_id` until the call
+/// completes. Today's MCP event source delivers arguments already parsed
+/// rather than as streamed JSON fragments, so in practice each call
+/// finalizes after a single `push`; the buffering exists so a future event
+/// source that streams partial JSON can reuse this path unchanged.
+#[derive(Default)]
+struct ToolArgsBuffer {
+    partial_json: std::collections::HashMap<String, String>,
+}
+
+impl ToolArgsBuffer {
+    fn push(&mut self, call_id: &str, fragment: &str) {
+        self.partial_json
+            .entry(call_id.to_string())
+            .or_default()
+            .push_str(fragment);
+    }
+
+    fn finalize(&mut self, call_id: &str) -> anyhow::Result<serde_json::Value> {
+        let raw = self.partial_json.remove(call_id).unwrap_or_default();
+        serde_json::from_str(&raw)
+            .with_context(|| format!("Failed to parse buffered tool arguments for call {}", call_id))
+    }
+}
+
+/// Spawn a background task that renders `StreamChunk`s live to the terminal
+/// and appends each one, as a JSON line, to `stream.jsonl` in
+/// `session_logs_dir` so a UI can tail the same feed the terminal shows.
+fn spawn_stream_renderer(
+    session_logs_dir: std::path::PathBuf,
+) -> tokio::sync::mpsc::UnboundedSender<StreamChunk> {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<StreamChunk>();
+    let stream_log_path = session_logs_dir.join("stream.jsonl");
+
+    tokio::spawn(async move {
+        use std::io::Write;
+
+        while let Some(chunk) = rx.recv().await {
+            match &chunk {
+                StreamChunk::TextDelta { text } => println!("🤖 Agent: {}", text),
+                StreamChunk::ReasoningDelta { text } => println!("🧠 Reasoning: {}", text),
+                StreamChunk::ToolArgsDelta { .. } => {}
+                StreamChunk::ToolArgsComplete { name, .. } => {
+                    println!("🔧 Tool args ready: {}", name);
+                }
+            }
+
+            if let Ok(line) = serde_json::to_string(&chunk) {
+                if let Ok(mut file) = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&stream_log_path)
+                {
+                    let _ = writeln!(file, "{}", line);
+                }
+            }
+        }
+    });
+
+    tx
+}
+
+/// One plugin's declaration in the config file's `plugins` section: a name
+/// to namespace its tools under, and the command used to spawn it.
+#[derive(Debug, Clone, serde::Deserialize)]
+struct PluginConfig {
+    name: String,
+    command: String,
+    #[serde(default)]
+    args: Vec<String>,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct PluginsFile {
+    #[serde(default)]
+    plugins: Vec<PluginConfig>,
+}
+
+/// A single tool a plugin exposes, as returned by its `describe` call.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+struct PluginToolSignature {
+    name: String,
+    description: String,
+    #[serde(default)]
+    arguments_schema: serde_json::Value,
+}
+
+/// A single line of the plugin JSON-RPC protocol sent on the plugin's
+/// stdin: `{"method": "describe"}` or `{"method": "invoke", "tool": "...",
+/// "arguments": {...}}`.
+#[derive(Debug, serde::Serialize)]
+struct PluginRpcRequest {
+    method: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    arguments: Option<serde_json::Value>,
+}
+
+/// A single line of the plugin JSON-RPC protocol read back on the plugin's
+/// stdout, in reply to a [`PluginRpcRequest`].
+#[derive(Debug, serde::Deserialize)]
+struct PluginRpcResponse {
+    #[serde(default)]
+    tools: Vec<PluginToolSignature>,
+    #[serde(default)]
+    result: serde_json::Value,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// A running plugin subprocess, talking line-delimited JSON-RPC over its
+/// piped stdin/stdout.
+struct PluginHandle {
+    name: String,
+    child: tokio::process::Child,
+    stdin: tokio::process::ChildStdin,
+    stdout: tokio::io::BufReader<tokio::process::ChildStdout>,
+}
+
+impl PluginHandle {
+    async fn spawn(config: &PluginConfig) -> anyhow::Result<Self> {
+        let mut child = tokio::process::Command::new(&config.command)
+            .args(&config.args)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to spawn plugin '{}'", config.name))?;
+
+        let stdin = child
+            .stdin
+            .take()
+            .with_context(|| format!("Plugin '{}' has no stdin pipe", config.name))?;
+        let stdout = child
+            .stdout
+            .take()
+            .with_context(|| format!("Plugin '{}' has no stdout pipe", config.name))?;
+
+        Ok(Self {
+            name: config.name.clone(),
+            child,
+            stdin,
+            stdout: tokio::io::BufReader::new(stdout),
+        })
+    }
+
+    /// Send one JSON-RPC request and read back one JSON-RPC response line.
+    async fn call(&mut self, request: PluginRpcRequest) -> anyhow::Result<PluginRpcResponse> {
+        use tokio::io::AsyncBufReadExt;
+        use tokio::io::AsyncWriteExt;
+
+        let line = serde_json::to_string(&request)
+            .with_context(|| format!("Failed to serialize request to plugin '{}'", self.name))?;
+        self.stdin
+            .write_all(format!("{}\n", line).as_bytes())
+            .await
+            .with_context(|| format!("Failed to write to plugin '{}' stdin", self.name))?;
+        self.stdin
+            .flush()
+            .await
+            .with_context(|| format!("Failed to flush plugin '{}' stdin", self.name))?;
+
+        let mut response_line = String::new();
+        self.stdout
+            .read_line(&mut response_line)
+            .await
+            .with_context(|| format!("Failed to read response from plugin '{}'", self.name))?;
+        if response_line.trim().is_empty() {
+            return Err(anyhow::anyhow!(
+                "Plugin '{}' closed its stdout without a response",
+                self.name
+            ));
+        }
+
+        serde_json::from_str(&response_line)
+            .with_context(|| format!("Failed to parse response from plugin '{}'", self.name))
+    }
+
+    async fn describe(&mut self) -> anyhow::Result<Vec<PluginToolSignature>> {
+        let response = self
+            .call(PluginRpcRequest {
+                method: "describe".to_string(),
+                tool: None,
+                arguments: None,
+            })
+            .await?;
+        Ok(response.tools)
+    }
+
+    async fn invoke(
+        &mut self,
+        tool_name: &str,
+        arguments: serde_json::Value,
+    ) -> anyhow::Result<serde_json::Value> {
+        let response = self
+            .call(PluginRpcRequest {
+                method: "invoke".to_string(),
+                tool: Some(tool_name.to_string()),
+                arguments: Some(arguments),
+            })
+            .await?;
+        if let Some(error) = response.error {
+            return Err(anyhow::anyhow!(
+                "Plugin '{}' tool '{}' returned an error: {}",
+                self.name,
+                tool_name,
+                error
+            ));
+        }
+        Ok(response.result)
+    }
+}
+
+impl Drop for PluginHandle {
+    fn drop(&mut self) {
+        let _ = self.child.start_kill();
+    }
+}
+
+/// Namespace a plugin's tool name as `plugin:<plugin_name>:<tool_name>` so
+/// tools from different plugins (or a plugin and a future MCP server) can
+/// never collide.
+fn plugin_tool_name(plugin_name: &str, tool_name: &str) -> String {
+    format!("plugin:{}:{}", plugin_name, tool_name)
+}
+
+fn is_plugin_tool(tool_name: &str) -> bool {
+    tool_name.starts_with("plugin:")
+}
+
+/// Holds every plugin spawned for the session and the namespaced tool
+/// signatures they described, and routes `invoke` calls back to the owning
+/// plugin by namespaced tool name.
+struct PluginRegistry {
+    handles: Vec<PluginHandle>,
+    tools: Vec<(String, PluginToolSignature)>,
+    tool_owner: std::collections::HashMap<String, usize>,
+}
+
+impl PluginRegistry {
+    /// Spawn every plugin declared in the config file's optional `plugins`
+    /// section and describe its tools. A plugin that fails to spawn or
+    /// describe is a hard error: a misconfigured plugin command is almost
+    /// always an operator mistake worth surfacing immediately rather than
+    /// silently running with fewer tools.
+    async fn spawn_from_config(config_content: &str) -> anyhow::Result<Self> {
+        let parsed: PluginsFile = serde_yaml::from_str(config_content)
+            .with_context(|| "Failed to parse `plugins` from config file")?;
+
+        let mut handles = Vec::new();
+        let mut tools = Vec::new();
+        let mut tool_owner = std::collections::HashMap::new();
+
+        for config in &parsed.plugins {
+            let mut handle = PluginHandle::spawn(config).await?;
+            let signatures = handle.describe().await.with_context(|| {
+                format!("Failed to describe tools for plugin '{}'", config.name)
+            })?;
+
+            let owner_index = handles.len();
+            for signature in signatures {
+                let namespaced_name = plugin_tool_name(&config.name, &signature.name);
+                tool_owner.insert(namespaced_name.clone(), owner_index);
+                tools.push((namespaced_name, signature));
+            }
+            handles.push(handle);
+        }
+
+        Ok(Self {
+            handles,
+            tools,
+            tool_owner,
+        })
+    }
+
+    fn namespaced_tools(&self) -> Vec<(String, PluginToolSignature)> {
+        self.tools.clone()
+    }
+
+    async fn invoke(
+        &mut self,
+        namespaced_name: &str,
+        arguments: serde_json::Value,
+    ) -> anyhow::Result<serde_json::Value> {
+        let owner_index = *self
+            .tool_owner
+            .get(namespaced_name)
+            .with_context(|| format!("No plugin registered for tool '{}'", namespaced_name))?;
+        let tool_name = namespaced_name
+            .rsplit(':')
+            .next()
+            .with_context(|| format!("Malformed namespaced tool name '{}'", namespaced_name))?;
+        self.handles[owner_index].invoke(tool_name, arguments).await
+    }
+}
+
+/// One hook command configured for a lifecycle event in the config file's
+/// `hooks` section.
+#[derive(Debug, Clone, serde::Deserialize)]
+struct HookConfig {
+    command: String,
+    #[serde(default)]
+    args: Vec<String>,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct HooksFile {
+    #[serde(default)]
+    hooks: std::collections::HashMap<String, Vec<HookConfig>>,
+}
+
+/// Load the optional `hooks` map from the config file: lifecycle event name
+/// (`iteration_start`, `context_summarized`, `tool_call_begin`, `tool_denied`,
+/// `bugcrowd_submit_approved`, `checkpoint_saved`, `session_end`) to the list
+/// of shell commands to run when that event fires.
+fn load_hook_table(
+    config_content: &str,
+) -> anyhow::Result<std::collections::HashMap<String, Vec<HookConfig>>> {
+    let parsed: HooksFile = serde_yaml::from_str(config_content)
+        .with_context(|| "Failed to parse `hooks` from config file")?;
+    Ok(parsed.hooks)
+}
+
+/// One named backend in the `providers` config section: an OpenAI-
+/// compatible (or Anthropic/Cohere-compatible) API declared by its
+/// `base_url` rather than hardcoded, in the spirit of aichat's
+/// `register_client!` registry.
+#[derive(Debug, Clone, serde::Deserialize)]
+struct ProviderConfig {
+    base_url: String,
+    #[serde(default)]
+    env_key: Option<String>,
+    #[serde(default = "default_wire_api")]
+    wire_api: String,
+    #[serde(default)]
+    query_params: Option<std::collections::HashMap<String, String>>,
+    #[serde(default)]
+    http_headers: Option<std::collections::HashMap<String, String>>,
+    /// Model-name prefixes (e.g. `"anthropic/"`, `"claude-"`) that route to
+    /// this provider when the model isn't given an explicit `provider:`
+    /// prefix.
+    #[serde(default)]
+    model_prefixes: Vec<String>,
+    /// Forwarded to the process as `HTTP_PROXY`/`HTTPS_PROXY` before the
+    /// client is built, since that's what the underlying reqwest client
+    /// already honors.
+    #[serde(default)]
+    http_proxy: Option<String>,
+}
+
+fn default_wire_api() -> String {
+    "chat".to_string()
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct ProvidersFile {
+    #[serde(default)]
+    providers: std::collections::HashMap<String, ProviderConfig>,
+    #[serde(default)]
+    default_provider: Option<String>,
+}
+
+/// Load the optional `providers` map from the config file: named backends
+/// (OpenAI, Anthropic, Cohere, a self-hosted OpenAI-compatible server, ...)
+/// with their `base_url`, `env_key`, `wire_api`, and optional
+/// `query_params`/`http_headers`/`model_prefixes`/`http_proxy`, plus an
+/// optional `default_provider` name used when a model matches none of them.
+/// Configs with no `providers` section fall back to the built-in
+/// OpenRouter provider exactly as before this was added.
+fn load_provider_registry(config_content: &str) -> anyhow::Result<ProvidersFile> {
+    serde_yaml::from_str(config_content).with_context(|| "Failed to parse `providers` from config file")
+}
+
+/// Resolve a `ModelProviderInfo` and the bare model name to send it for
+/// `model`: an explicit `provider:model` prefix wins, then a configured
+/// provider whose `model_prefixes` contains a prefix of the model name,
+/// then `default_provider`, and finally the long-standing built-in
+/// OpenRouter provider. Which provider wins when several providers'
+/// `model_prefixes` match is not defined.
+fn resolve_provider_for_model(
+    registry: &ProvidersFile,
+    model: &str,
+) -> anyhow::Result<(codex_core::model_provider_info::ModelProviderInfo, String)> {
+    use codex_core::model_provider_info::ModelProviderInfo;
+    use codex_core::model_provider_info::WireApi;
+
+    let (explicit_name, bare_model) = match model.split_once(':') {
+        Some((name, rest)) if registry.providers.contains_key(name) => (Some(name), rest),
+        _ => (None, model),
+    };
+
+    let matched = explicit_name
+        .map(|name| (name, &registry.providers[name]))
+        .or_else(|| {
+            registry
+                .providers
+                .iter()
+                .find(|(_, config)| {
+                    config
+                        .model_prefixes
+                        .iter()
+                        .any(|prefix| bare_model.starts_with(prefix.as_str()))
+                })
+                .map(|(name, config)| (name.as_str(), config))
+        })
+        .or_else(|| {
+            registry
+                .default_provider
+                .as_deref()
+                .and_then(|name| registry.providers.get(name).map(|config| (name, config)))
+        });
+
+    let Some((name, config)) = matched else {
+        return Ok((
+            ModelProviderInfo {
+                name: "OpenRouter".to_string(),
+                base_url: Some("https://openrouter.ai/api/v1".to_string()),
+                env_key: Some("OPENROUTER_API_KEY".to_string()),
+                env_key_instructions: None,
+                wire_api: WireApi::Chat,
+                query_params: None,
+                env_http_headers: None,
+                http_headers: None,
+                request_max_retries: Some(3),
+                stream_max_retries: Some(5),
+                stream_idle_timeout_ms: Some(30000),
+                requires_openai_auth: false,
+            },
+            bare_model.to_string(),
+        ));
+    };
+
+    if let Some(proxy_url) = &config.http_proxy {
+        std::env::set_var("HTTPS_PROXY", proxy_url);
+        std::env::set_var("HTTP_PROXY", proxy_url);
+    }
+
+    let wire_api = match config.wire_api.as_str() {
+        "chat" => WireApi::Chat,
+        "responses" => WireApi::Responses,
+        other => {
+            return Err(anyhow::anyhow!(
+                "Unknown wire_api '{}' for provider '{}' (expected 'chat' or 'responses')",
+                other,
+                name
+            ))
+        }
+    };
+
+    Ok((
+        ModelProviderInfo {
+            name: name.to_string(),
+            base_url: Some(config.base_url.clone()),
+            env_key: config.env_key.clone(),
+            env_key_instructions: None,
+            wire_api,
+            query_params: config.query_params.clone(),
+            env_http_headers: None,
+            http_headers: config.http_headers.clone(),
+            request_max_retries: Some(3),
+            stream_max_retries: Some(5),
+            stream_idle_timeout_ms: Some(30000),
+            requires_openai_auth: false,
+        },
+        bare_model.to_string(),
+    ))
+}
+
+/// Spawn one hook command, writing `payload_str` to its stdin and capturing
+/// its combined stdout/stderr/exit status.
+fn run_one_hook(hook: &HookConfig, payload_str: &str) -> anyhow::Result<std::process::Output> {
+    use std::io::Write;
+
+    let mut child = std::process::Command::new(&hook.command)
+        .args(&hook.args)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to spawn hook command '{}'", hook.command))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(payload_str.as_bytes())
+            .with_context(|| format!("Failed to write payload to hook '{}'", hook.command))?;
+    }
+
+    child
+        .wait_with_output()
+        .with_context(|| format!("Failed to wait on hook '{}'", hook.command))
+}
+
+/// Run every hook configured for `event_name`, feeding `payload` as JSON on
+/// each hook's stdin and appending its exit status and captured
+/// stdout/stderr to `hooks.jsonl` in `session_logs_dir`. Returns `true` if
+/// any hook exited non-zero, so a `tool_call_begin` hook can veto the call
+/// the same way a denied approval does; the return value is ignored for
+/// every other event.
+fn run_lifecycle_hooks(
+    hook_table: &std::collections::HashMap<String, Vec<HookConfig>>,
+    event_name: &str,
+    payload: &serde_json::Value,
+    session_logs_dir: &std::path::Path,
+) -> bool {
+    let Some(hooks) = hook_table.get(event_name) else {
+        return false;
+    };
+
+    let payload_str = serde_json::to_string(payload).unwrap_or_default();
+    let hooks_log_path = session_logs_dir.join("hooks.jsonl");
+    let mut any_failed = false;
+
+    for hook in hooks {
+        let outcome = run_one_hook(hook, &payload_str);
+        let (success, stdout, stderr, error) = match &outcome {
+            Ok(output) => (
+                output.status.success(),
+                String::from_utf8_lossy(&output.stdout).to_string(),
+                String::from_utf8_lossy(&output.stderr).to_string(),
+                None,
+            ),
+            Err(e) => (false, String::new(), String::new(), Some(e.to_string())),
+        };
+
+        if !success {
+            any_failed = true;
+            println!(
+                "⚠️ Hook '{}' for event '{}' {}",
+                hook.command,
+                event_name,
+                error.as_deref().unwrap_or("exited non-zero")
+            );
+        }
+
+        let log_entry = serde_json::json!({
+            "event": event_name,
+            "command": hook.command,
+            "success": success,
+            "stdout": stdout,
+            "stderr": stderr,
+            "error": error,
+        });
+        if let Ok(line) = serde_json::to_string(&log_entry) {
+            use std::io::Write;
+            if let Ok(mut file) = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&hooks_log_path)
+            {
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+    }
+
+    any_failed
+}
+
+async fn run_autonomous_mode(
+    autonomous_cli: AutonomousCommand,
+    _codex_linux_sandbox_exe: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    use codex_core::ConversationManager;
+    use codex_core::config::Config;
+    use codex_core::protocol::InputItem;
+    use codex_core::protocol::Op;
+    use codex_login::AuthManager;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use std::time::Instant;
+    use tokio::time::sleep;
+
+    println!("🚀 Starting autonomous mode...");
+    println!("📁 Config file: {:?}", autonomous_cli.config_file);
+    if let Some(ref resume_dir) = autonomous_cli.resume_dir {
+        println!("🔄 Resuming from: {:?}", resume_dir);
+    }
+
+    // Load config file
+    let config_content =
+        std::fs::read_to_string(&autonomous_cli.config_file).with_context(|| {
+            format!(
+                "Failed to read config file: {:?}",
+                autonomous_cli.config_file
+            )
+        })?;
+
+    // Load the tool approval policy table (if the config file declares one)
+    // so tool-call approvals can be decided per-tool instead of hardcoding a
+    // single submit tool.
+    let tool_policy_table = load_tool_policy_table(&config_content)?;
+    println!(
+        "🛡️  Loaded {} tool policy override(s)",
+        tool_policy_table.len()
+    );
+
+    // Spawn any plugin executables declared in the config file's `plugins`
+    // section so their tools are available to the driver model alongside
+    // the built-in note/Slack/finish tools and codex's own MCP tools.
+    let mut plugin_registry = PluginRegistry::spawn_from_config(&config_content).await?;
+    println!(
+        "🔌 Loaded {} plugin tool(s)",
+        plugin_registry.namespaced_tools().len()
+    );
+
+    // Load the optional lifecycle hook table so operators can wire
+    // notifications, external logging, or custom guardrails into the loop
+    // without patching it.
+    let hook_table = load_hook_table(&config_content)?;
+    println!(
+        "🪝 Loaded hook(s) for {} lifecycle event(s)",
+        hook_table.len()
+    );
+
+    // Load the optional, ordered approval-hook pipeline: matcher + action
+    // entries checked before falling back to the tool policy table (for
+    // exec approvals) or straight to the external LLM (for patches).
+    let approval_hooks = load_approval_hooks(&config_content)?;
+    println!("🧭 Loaded {} approval hook(s)", approval_hooks.len());
+
+    // Load prompt templates from core directory
+    let core_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap()
+        .join("core");
+
+    let initial_prompt_file = core_dir.join("initial_prompt.txt");
+    let continuation_prompt_file = core_dir.join("continuation_prompt.txt");
+    let approval_prompt_file = core_dir.join("approval_prompt.txt");
+    let bugcrowd_approval_prompt_file = core_dir.join("bugcrowd_approval_prompt.txt");
+    let summarization_prompt_file = core_dir.join("summarization_prompt.txt");
+
+    let initial_prompt_template =
+        std::fs::read_to_string(&initial_prompt_file).with_context(|| {
+            format!(
+                "Failed to read initial prompt file: {:?}",
+                initial_prompt_file
+            )
+        })?;
+
+    let continuation_prompt_template = std::fs::read_to_string(&continuation_prompt_file)
+        .with_context(|| {
+            format!(
+                "Failed to read continuation prompt file: {:?}",
+                continuation_prompt_file
+            )
+        })?;
+
+    let approval_prompt_template =
+        std::fs::read_to_string(&approval_prompt_file).with_context(|| {
+            format!(
+                "Failed to read approval prompt file: {:?}",
+                approval_prompt_file
+            )
+        })?;
+
+    let bugcrowd_approval_prompt_template = std::fs::read_to_string(&bugcrowd_approval_prompt_file)
+        .with_context(|| {
+            format!(
+                "Failed to read bugcrowd approval prompt file: {:?}",
+                bugcrowd_approval_prompt_file
+            )
+        })?;
+
+    let summarization_prompt_template = std::fs::read_to_string(&summarization_prompt_file)
+        .with_context(|| {
+            format!(
+                "Failed to read summarization prompt file: {:?}",
+                summarization_prompt_file
+            )
+        })?;
+
+    println!("📋 Task config loaded");
+    println!("📝 Prompt templates loaded");
+
+    // Resolve the named profile (if any) and layer the individual CLI flags
+    // on top of it; a flag that was actually passed always wins over the
+    // profile, which in turn wins over the hardcoded defaults below.
+    let profile = match &autonomous_cli.profile {
+        Some(name) => Some(load_autonomous_profile(&config_content, name)?),
+        None => None,
+    };
+
+    let driver_model = autonomous_cli
+        .driver_model
+        .clone()
+        .or_else(|| profile.as_ref().and_then(|p| p.driver_model.clone()))
+        .unwrap_or_else(|| "o3".to_string());
+    let duration = autonomous_cli
+        .duration
+        .or_else(|| profile.as_ref().and_then(|p| p.duration))
+        .unwrap_or(30);
+    let work_start_hour = autonomous_cli
+        .work_start_hour
+        .or_else(|| profile.as_ref().and_then(|p| p.work_start_hour))
+        .unwrap_or(0);
+    let work_end_hour = autonomous_cli
+        .work_end_hour
+        .or_else(|| profile.as_ref().and_then(|p| p.work_end_hour))
+        .unwrap_or(23);
+    let mut max_context_tokens = autonomous_cli
+        .max_context_tokens
+        .or_else(|| profile.as_ref().and_then(|p| p.max_context_tokens))
+        .unwrap_or(200_000);
+    let full_auto =
+        autonomous_cli.full_auto || profile.as_ref().and_then(|p| p.full_auto).unwrap_or(false);
+    let mode = autonomous_cli
+        .mode
+        .clone()
+        .or_else(|| profile.as_ref().and_then(|p| p.mode.clone()));
+    let tool_confirmation_mode = match autonomous_cli
+        .tool_confirmation_mode
+        .clone()
+        .or_else(|| profile.as_ref().and_then(|p| p.tool_confirmation_mode.clone()))
+    {
+        Some(value) => parse_tool_confirmation_mode(&value)?,
+        None => ToolConfirmationMode::Auto,
+    };
+
+    println!(
+        "🔧 Profile: {} (work hours {:02}:00-{:02}:00)",
+        autonomous_cli.profile.as_deref().unwrap_or("<none>"),
+        work_start_hour,
+        work_end_hour
+    );
+    println!("⏰ Duration: {} minutes", duration);
+    println!("🤖 Driver model: {}", driver_model);
+    println!("🔐 Tool confirmation mode: {:?}", tool_confirmation_mode);
+
+    // Create codex config with overrides, applying full-auto settings if enabled
+    let mut config_overrides = codex_core::config::ConfigOverrides::default();
+    if full_auto {
+        config_overrides.approval_policy = Some(codex_core::protocol::AskForApproval::OnFailure);
+        config_overrides.sandbox_mode =
+            Some(codex_protocol::config_types::SandboxMode::WorkspaceWrite);
+    } else if let Some(profile) = &profile {
+        if let Some(policy) = &profile.approval_policy {
+            config_overrides.approval_policy = Some(parse_approval_policy(policy)?);
+        }
+        if let Some(sandbox) = &profile.sandbox_mode {
+            config_overrides.sandbox_mode = Some(parse_sandbox_mode(sandbox)?);
+        }
+    }
+
+    // Set specialist mode if provided
+    if let Some(mode) = mode.clone() {
+        config_overrides.specialist = Some(mode);
+    }
+
+    let config = Config::load_with_cli_overrides(
+        autonomous_cli
+            .config_overrides
+            .parse_overrides()
+            .map_err(anyhow::Error::msg)?,
+        config_overrides,
+    )
+    .with_context(|| "Failed to load codex config")?;
+
+    // Debug: Log the actual config being used
+    println!(
+        "🔧 DEBUG: Loaded config - model: {}, provider: {}",
+        config.model, config.model_provider.name
+    );
+    println!("🔧 DEBUG: Driver model: {}", driver_model);
+    println!(
+        "🔧 DEBUG: OPENROUTER_API_KEY: {}",
+        if std::env::var("OPENROUTER_API_KEY").is_ok() {
+            "SET"
+        } else {
+            "NOT SET"
+        }
+    );
+    println!(
+        "🔧 DEBUG: OPENAI_API_KEY: {}",
+        if std::env::var("OPENAI_API_KEY").is_ok() {
+            "SET"
+        } else {
+            "NOT SET"
+        }
+    );
+
+    // Initialize codex session
+    let codex_home = codex_core::config::find_codex_home()?;
+    let auth_manager = Arc::new(AuthManager::new(codex_home, codex_login::AuthMode::ChatGPT));
+    let conversation_manager = ConversationManager::new(auth_manager);
+    let new_conversation = conversation_manager
+        .new_conversation(config.clone())
+        .await?;
+    let codex = new_conversation.conversation;
+    println!("✅ Codex session initialized");
+
+    // Initialize context accumulator and conversation log
+    let mut context = String::new();
+    let mut conversation_log = Vec::new();
+    let mut compaction_events: Vec<serde_json::Value> = Vec::new();
+    // Cache of already-executed driver tool calls for this session, keyed on
+    // tool name + serialized arguments, so `run_driver_tool_loop` doesn't
+    // redo identical note reads/writes across a multi-step tool loop.
+    let mut driver_tool_cache: std::collections::HashMap<String, serde_json::Value> =
+        std::collections::HashMap::new();
+    let mut iteration = 0;
+
+    // Load resume context if resume directory is provided
+    if let Some(ref resume_dir) = autonomous_cli.resume_dir {
+        println!("🔄 Loading resume context from {:?}", resume_dir);
+
+        // Load context from context_log.txt
+        let context_log_file = resume_dir.join("context_log.txt");
+        if context_log_file.exists() {
+            context = std::fs::read_to_string(&context_log_file)
+                .with_context(|| format!("Failed to read context log: {:?}", context_log_file))?;
+            println!("✅ Context log loaded ({} bytes)", context.len());
+        }
+
+        // Load conversation from latest.json
+        let latest_file = resume_dir.join("latest.json");
+        if latest_file.exists() {
+            let latest_content = std::fs::read_to_string(&latest_file)
+                .with_context(|| format!("Failed to read latest.json: {:?}", latest_file))?;
+            conversation_log = serde_json::from_str(&latest_content)
+                .with_context(|| format!("Failed to parse latest.json: {:?}", latest_file))?;
+            println!(
+                "✅ Conversation log loaded ({} messages)",
+                conversation_log.len()
+            );
+        }
+
+        // Load compaction history, if any, so resumed sessions don't
+        // rediscover already-summarized context from scratch.
+        let compaction_log_file = resume_dir.join("compaction_log.json");
+        if compaction_log_file.exists() {
+            let compaction_content = std::fs::read_to_string(&compaction_log_file)
+                .with_context(|| {
+                    format!("Failed to read compaction log: {:?}", compaction_log_file)
+                })?;
+            compaction_events = serde_json::from_str(&compaction_content).with_context(|| {
+                format!("Failed to parse compaction log: {:?}", compaction_log_file)
+            })?;
+            println!(
+                "✅ Compaction history loaded ({} events)",
+                compaction_events.len()
+            );
+        }
+
+        // Determine next iteration number from existing files
+        let mut max_iteration = 0;
+        if let Ok(entries) = std::fs::read_dir(resume_dir) {
+            for entry in entries {
+                if let Ok(entry) = entry {
+                    let filename = entry.file_name().to_string_lossy().to_string();
+                    if filename.starts_with("iteration_") && filename.ends_with(".json") {
+                        if let Ok(iter_num) = filename[10..13].parse::<u32>() {
+                            max_iteration = max_iteration.max(iter_num);
+                        }
+                    }
+                }
+            }
+        }
+        iteration = max_iteration + 1;
+        println!("✅ Resuming from iteration {}", iteration);
+    }
+    let start_time = Instant::now();
+    let _duration = Duration::from_secs(duration * 60);
+
+    // Create or use existing session-specific logs directory
+    let session_timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    let session_logs_dir = if let Some(ref resume_dir) = autonomous_cli.resume_dir {
+        // Use existing directory for resume
+        resume_dir.clone()
     } else if let Some(ref custom_logs_dir) = autonomous_cli.logs_dir {
         // Use custom logs directory (for vulnerability deep-dives)
         std::fs::create_dir_all(&custom_logs_dir).with_context(|| {
@@ -509,6 +2195,12 @@ async fn run_autonomous_mode(
     })?;
     println!("📁 Backup logs directory: {:?}", backup_logs_dir);
 
+    // Render model output live as it streams in, instead of only showing it
+    // once a whole message/reasoning block or tool call has finished, and
+    // keep an incremental `stream.jsonl` alongside the end-of-iteration
+    // `context_log.txt` so a UI can tail the same feed.
+    let stream_tx = spawn_stream_renderer(session_logs_dir.clone());
+
     // Load codex system prompt from prompt.md (only for new sessions)
     if autonomous_cli.resume_dir.is_none() {
         let prompt_md_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
@@ -526,10 +2218,39 @@ async fn run_autonomous_mode(
         }));
     }
 
+    // If requested, start the observability/steering tunnel and publish
+    // every checkpoint to it on the same cadence as the on-disk logs.
+    let observability_hub = autonomous_cli.serve.clone().map(|addr| {
+        let hub = ObservabilityHub::new(autonomous_cli.serve_token.clone());
+        spawn_observability_server(addr, hub.clone());
+        hub
+    });
+    let remote_approval_timeout =
+        std::time::Duration::from_secs(autonomous_cli.remote_approval_timeout_secs);
+
+    // If requested, block on an external task queue instead of looping on a
+    // fixed duration.
+    let mut task_queue = autonomous_cli.watch.clone().map(spawn_task_watcher);
+
     // Function to save checkpoint log files and update heartbeat
-    let save_checkpoint = |log: &Vec<serde_json::Value>, iteration_num: u32| {
+    let save_checkpoint = |log: &Vec<serde_json::Value>,
+                           compaction_log: &Vec<serde_json::Value>,
+                           iteration_num: u32| {
         let log_json = serde_json::to_string_pretty(log).unwrap_or_else(|_| "[]".to_string());
 
+        // Persist compaction history so `--resume-dir` reloads the
+        // already-summarized log rather than re-growing it from scratch.
+        let compaction_json =
+            serde_json::to_string_pretty(compaction_log).unwrap_or_else(|_| "[]".to_string());
+        let compaction_path = session_logs_dir.join("compaction_log.json");
+        let backup_compaction_path = backup_logs_dir.join("compaction_log.json");
+        if let Err(e) = std::fs::write(&compaction_path, &compaction_json) {
+            eprintln!("❌ Failed to save compaction log: {}", e);
+        }
+        if let Err(e) = std::fs::write(&backup_compaction_path, &compaction_json) {
+            eprintln!("❌ Failed to save backup compaction log: {}", e);
+        }
+
         // Save numbered checkpoint to both locations
         let checkpoint_path = session_logs_dir.join(format!("iteration_{:03}.json", iteration_num));
         let backup_checkpoint_path =
@@ -606,13 +2327,22 @@ async fn run_autonomous_mode(
             "status": "running",
             "pid": std::process::id(),
             "config_file": autonomous_cli.config_file.to_string_lossy(),
-            "duration_minutes": autonomous_cli.duration,
-            "driver_model": &autonomous_cli.driver_model,
-            "full_auto": autonomous_cli.full_auto
+            "duration_minutes": duration,
+            "driver_model": &driver_model,
+            "full_auto": full_auto,
+            "profile": autonomous_cli.profile,
+            "tool_confirmation_mode": tool_confirmation_mode_as_flag_str(tool_confirmation_mode),
+            "max_context_tokens": max_context_tokens,
+            "serve": autonomous_cli.serve,
+            "serve_token_set": autonomous_cli.serve_token.is_some()
         });
 
         let heartbeat_json = serde_json::to_string_pretty(&heartbeat).unwrap_or_default();
 
+        if let Some(hub) = &observability_hub {
+            hub.publish_checkpoint(&heartbeat, log);
+        }
+
         // Save heartbeat in session directory and backup
         let heartbeat_path = session_logs_dir.join("heartbeat.json");
         let backup_heartbeat_path = backup_logs_dir.join("heartbeat.json");
@@ -639,19 +2369,63 @@ async fn run_autonomous_mode(
         if let Err(e) = std::fs::write(&backup_global_heartbeat_path, &heartbeat_json) {
             eprintln!("❌ Failed to save backup global heartbeat: {}", e);
         }
+
+        run_lifecycle_hooks(
+            &hook_table,
+            "checkpoint_saved",
+            &serde_json::json!({
+                "iteration": iteration_num,
+                "checkpoint_path": checkpoint_path.to_string_lossy(),
+                "heartbeat_path": heartbeat_path.to_string_lossy(),
+            }),
+            &session_logs_dir,
+        );
     };
 
     // Save initial checkpoint with system message
-    save_checkpoint(&conversation_log, 0);
+    save_checkpoint(&conversation_log, &compaction_events, 0);
     println!(
         "🚀 Session {} started with {} minute duration",
-        session_timestamp, autonomous_cli.duration
+        session_timestamp, duration
     );
 
-    // Main autonomous loop with error handling
-    let session_finished = false;
-    let loop_result = async {
-        while !session_finished {
+    // Main autonomous loop with error handling
+    let mut session_finished = false;
+    let loop_result = async {
+        while !session_finished {
+            // In `--watch` mode, block for the next task file instead of
+            // proceeding immediately; its contents seed this iteration's
+            // prompt and it's archived into the session logs once dequeued.
+            let mut watched_task_content: Option<String> = None;
+            if let Some(queue) = &mut task_queue {
+                println!("👀 Waiting for next task file in {:?}...", autonomous_cli.watch.as_ref().unwrap());
+                match queue.recv().await {
+                    Some(task_path) => match std::fs::read_to_string(&task_path) {
+                        Ok(content) => {
+                            println!("📥 Dequeued task file: {:?}", task_path);
+                            let archive_dir = session_logs_dir.join("watched_tasks");
+                            if let Err(e) = std::fs::create_dir_all(&archive_dir) {
+                                eprintln!("❌ Failed to create watched_tasks dir: {}", e);
+                            } else if let Some(file_name) = task_path.file_name() {
+                                let archived_path = archive_dir.join(file_name);
+                                if let Err(e) = std::fs::rename(&task_path, &archived_path) {
+                                    eprintln!("❌ Failed to archive task file {:?}: {}", task_path, e);
+                                }
+                            }
+                            watched_task_content = Some(content);
+                        }
+                        Err(e) => {
+                            eprintln!("❌ Failed to read task file {:?}: {}", task_path, e);
+                            continue;
+                        }
+                    },
+                    None => {
+                        println!("👀 Task watcher channel closed, stopping autonomous loop");
+                        break;
+                    }
+                }
+            }
+
             iteration += 1;
             println!(
                 "\n🔄 Iteration {} ({}s elapsed)",
@@ -659,6 +2433,16 @@ async fn run_autonomous_mode(
                 start_time.elapsed().as_secs()
             );
 
+            run_lifecycle_hooks(
+                &hook_table,
+                "iteration_start",
+                &serde_json::json!({
+                    "iteration": iteration,
+                    "elapsed_seconds": start_time.elapsed().as_secs(),
+                }),
+                &session_logs_dir,
+            );
+
             // Determine which prompt template to use
             let prompt_template = if iteration == 1 {
                 &initial_prompt_template
@@ -666,33 +2450,42 @@ async fn run_autonomous_mode(
                 &continuation_prompt_template
             };
 
-            // Check context token count and summarize if needed
-            let mut final_context = context.clone();
-            let mut context_was_summarized = false;
-            let context_tokens = count_tokens(&context)?;
-            const MAX_TOKENS: usize = 200_000;
-            const TOKEN_BUFFER: usize = 500;
+            if let Some(hub) = &observability_hub {
+                if let Some(override_value) = hub.take_max_tokens_override() {
+                    println!(
+                        "🛰️  Applying operator max_context_tokens override: {} -> {}",
+                        max_context_tokens, override_value
+                    );
+                    max_context_tokens = override_value;
+                }
+            }
 
-            if context_tokens > (MAX_TOKENS - TOKEN_BUFFER) {
+            // Compact the conversation log if it's approaching the configured
+            // token budget: summarize the oldest non-system messages and
+            // splice the summary back in as a single synthetic system
+            // message, preserving the original system prompt and the most
+            // recent messages verbatim.
+            if let Some(event) = compact_conversation_log_if_needed(
+                &mut conversation_log,
+                max_context_tokens,
+                &driver_model,
+                &summarization_prompt_template,
+                &config_content,
+            )
+            .await?
+            {
                 println!(
-                    "⚠️  Context approaching token limit: {} tokens (max: {})",
-                    context_tokens, MAX_TOKENS
+                    "✅ Compacted {} messages ({} tokens) into a summary",
+                    event["messages_removed"], event["tokens_before"]
                 );
+                context = build_readable_context(&conversation_log);
+                run_lifecycle_hooks(&hook_table, "context_summarized", &event, &session_logs_dir);
+                compaction_events.push(event);
+            }
 
-                // Summarize the formatted context string (but keep conversation_log intact)
-                final_context = summarize_context(
-                    &context,
-                    &autonomous_cli.driver_model,
-                    &summarization_prompt_template,
-                )
-                .await?;
-
-                context_was_summarized = true;
-                println!(
-                    "✅ Context summarized from {} to {} tokens",
-                    context_tokens,
-                    count_tokens(&final_context)?
-                );
+            let mut final_context = context.clone();
+            if let Some(task_content) = &watched_task_content {
+                final_context = format!("{}\n\nWATCHED TASK:\n{}", final_context, task_content);
             }
 
             // Inject config and context into prompt template
@@ -703,270 +2496,1211 @@ async fn run_autonomous_mode(
             let driver_prompt_tokens = count_tokens(&driver_prompt)?;
             println!("📊 Driver prompt tokens: {}", driver_prompt_tokens);
 
-            if driver_prompt_tokens > (MAX_TOKENS - TOKEN_BUFFER) {
+            if driver_prompt_tokens > (max_context_tokens - TOKEN_BUFFER) {
                 return Err(anyhow::anyhow!(
-                    "Driver prompt still too long after summarization: {} tokens (max: {})",
+                    "Driver prompt still too long after compaction: {} tokens (max: {})",
                     driver_prompt_tokens,
-                    MAX_TOKENS - TOKEN_BUFFER
+                    max_context_tokens - TOKEN_BUFFER
                 ));
             }
 
-            // Generate user prompt using external LLM
-            let (user_prompt, tool_results) =
-                generate_user_prompt(&driver_prompt, &autonomous_cli.driver_model, &session_logs_dir).await?;
+            // Drive the supervisor model through as many tool-call round
+            // trips as it asks for (executing each tool call and feeding the
+            // result back) before handing a plain-text instruction to codex.
+            let (final_user_prompt, driver_session_finished) = run_driver_tool_loop(
+                &driver_prompt,
+                &driver_model,
+                &session_logs_dir,
+                &config_content,
+                &mut conversation_log,
+                &mut driver_tool_cache,
+                &mut plugin_registry,
+                &tool_policy_table,
+                &bugcrowd_approval_prompt_template,
+                &hook_table,
+                observability_hub.as_ref(),
+                remote_approval_timeout,
+                tool_confirmation_mode,
+            )
+            .await?;
+            if driver_session_finished {
+                session_finished = true;
+            }
+
+            // Submit to codex, prepending any steering messages an operator
+            // sent over the observability tunnel since the last iteration.
+            let mut input_items = Vec::new();
+            if let Some(hub) = &observability_hub {
+                for steering_message in hub.drain_steering_messages() {
+                    println!("🛰️  Injecting operator steering message: {}", steering_message);
+                    input_items.push(InputItem::Text {
+                        text: format!("[operator steering message] {}", steering_message),
+                    });
+                }
+            }
+            input_items.push(InputItem::Text {
+                text: final_user_prompt.clone(),
+            });
+            let submission_id: String = codex.submit(Op::UserInput { items: input_items }).await?;
+
+            // Collect codex response and tool calls
+            let (codex_response, tool_calls, reasoning, tool_responses) =
+                collect_codex_response_with_tools(
+                    &codex,
+                    &submission_id,
+                    full_auto,
+                    &driver_model,
+                    &approval_prompt_template,
+                    &bugcrowd_approval_prompt_template,
+                    &session_logs_dir,
+                    &config_content,
+                    &tool_policy_table,
+                    &stream_tx,
+                    &hook_table,
+                    observability_hub.as_ref(),
+                    &approval_hooks,
+                    &config.model_provider,
+                    remote_approval_timeout,
+                )
+                .await?;
+
+            println!("🤖 Codex response collected");
 
-            println!("💭 Generated user prompt: {}", user_prompt);
+            // Add events in correct chronological order:
 
-            // Handle supervisor LLM tool calls and generate final user prompt
-            let final_user_prompt = if !tool_results.is_empty() {
-                // Case 2: Supervisor made tool calls - need to get follow-up response
+            // 1. Assistant reasoning (if present)
+            if let Some(reasoning_text) = reasoning {
+                conversation_log.push(serde_json::json!({
+                    "role": "assistant",
+                    "content": "",
+                    "reasoning": reasoning_text
+                }));
+            }
 
-                // Add user message with tool calls to conversation log
+            // 2. Assistant tool calls (if any)
+            if !tool_calls.is_empty() {
                 conversation_log.push(serde_json::json!({
-                    "role": "user",
-                    "content": user_prompt,
-                    "tool_calls": tool_results.iter().map(|tr| {
-                        // Find the original tool call to get the correct tool name
-                        let tool_call_id = tr["tool_call_id"].as_str().unwrap_or("");
-                        let tool_name = tr.get("tool_name").and_then(|n| n.as_str()).unwrap_or("unknown");
-                        serde_json::json!({
-                            "id": tool_call_id,
-                            "type": "function",
-                            "function": {
-                                "name": tool_name,
-                                "arguments": serde_json::json!({})
-                            }
-                        })
-                    }).collect::<Vec<_>>()
+                    "role": "assistant",
+                    "content": "",
+                    "tool_calls": tool_calls
                 }));
+            }
 
-                // Add tool results to conversation log
-                for tool_result in &tool_results {
-                    conversation_log.push(serde_json::json!({
-                        "role": "tool",
-                        "tool_call_id": tool_result["tool_call_id"],
-                        "content": tool_result["content"]
-                    }));
+            // 3. Tool responses
+            for tool_response in tool_responses {
+                conversation_log.push(tool_response);
+            }
+
+            // 4. Final assistant response
+            conversation_log.push(serde_json::json!({
+                "role": "assistant",
+                "content": codex_response
+            }));
+
+            // Rebuild the readable context from the (possibly compacted) conversation log.
+            context = build_readable_context(&conversation_log);
+
+            // Save context string to file for testing
+            let context_log_path = session_logs_dir.join("context_log.txt");
+            if let Err(e) = std::fs::write(&context_log_path, &context) {
+                eprintln!("❌ Failed to save context log: {}", e);
+            }
+
+            // Save checkpoint after each iteration
+            save_checkpoint(&conversation_log, &compaction_events, iteration as u32);
+
+            if let Some(hub) = &observability_hub {
+                if hub.should_exit() {
+                    println!("🛰️  Exiting after iteration {} per operator request", iteration);
+                    break;
+                }
+            }
+
+            // Wait before next iteration, polling the control channel so an
+            // operator's pause/resume takes effect between iterations
+            // instead of only at the end of a fixed sleep.
+            let mut waited = Duration::from_secs(0);
+            while waited < Duration::from_secs(10) {
+                sleep(Duration::from_secs(1)).await;
+                waited += Duration::from_secs(1);
+                if let Some(hub) = &observability_hub {
+                    while hub.is_paused() {
+                        sleep(Duration::from_secs(1)).await;
+                    }
+                }
+            }
+        }
+
+        println!(
+            "✅ Autonomous mode completed after {} iterations",
+            iteration
+        );
+        Ok::<(), anyhow::Error>(())
+    }
+    .await;
+
+    // Save final checkpoint regardless of how we exit
+    save_checkpoint(&conversation_log, &compaction_events, iteration as u32);
+
+    // Update final heartbeat with completion status
+    let final_status = if loop_result.is_ok() {
+        "completed"
+    } else {
+        "error"
+    };
+
+    if let Err(e) = &loop_result {
+        let last_tool_call = conversation_log
+            .iter()
+            .rev()
+            .find_map(|entry| entry.get("tool_calls").and_then(|v| v.as_array()))
+            .and_then(|tool_calls| tool_calls.last())
+            .and_then(|tool_call| tool_call["function"]["name"].as_str());
+        report_session_crash(e, &session_logs_dir, None, Some(iteration as u32), last_tool_call).await;
+    }
+    let final_heartbeat = serde_json::json!({
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "iteration": iteration,
+        "session_timestamp": session_timestamp,
+        "elapsed_seconds": start_time.elapsed().as_secs(),
+        "status": final_status,
+        "pid": std::process::id(),
+        "config_file": autonomous_cli.config_file.to_string_lossy(),
+        "duration_minutes": duration,
+        "driver_model": &driver_model,
+        "full_auto": full_auto,
+        "profile": autonomous_cli.profile,
+        "tool_confirmation_mode": tool_confirmation_mode_as_flag_str(tool_confirmation_mode),
+        "max_context_tokens": max_context_tokens,
+        "serve": autonomous_cli.serve,
+        "serve_token_set": autonomous_cli.serve_token.is_some()
+    });
+
+    let final_heartbeat_json = serde_json::to_string_pretty(&final_heartbeat).unwrap_or_default();
+    let global_heartbeat_path = PathBuf::from("./logs/latest_session_heartbeat.json");
+    if let Err(e) = std::fs::write(&global_heartbeat_path, &final_heartbeat_json) {
+        eprintln!("❌ Failed to save final heartbeat: {}", e);
+    }
+
+    println!(
+        "🏁 Final checkpoint saved for session {}",
+        session_timestamp
+    );
+
+    run_lifecycle_hooks(
+        &hook_table,
+        "session_end",
+        &serde_json::json!({
+            "status": final_status,
+            "iteration": iteration,
+            "elapsed_seconds": start_time.elapsed().as_secs(),
+        }),
+        &session_logs_dir,
+    );
+
+    // Return the result
+    loop_result
+}
+
+/// One autonomous session discovered under a logs root, keyed by its
+/// `autonomous_session_<timestamp>` directory name.
+struct DiscoveredSession {
+    name: String,
+    dir: PathBuf,
+    heartbeat: serde_json::Value,
+}
+
+fn backup_logs_root() -> anyhow::Result<PathBuf> {
+    Ok(dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Could not find home directory"))?
+        .join("codex-logs-backup"))
+}
+
+/// Scan `./logs` and the backup logs directory for `autonomous_session_*`
+/// directories and parse each one's `heartbeat.json`. `./logs` is preferred
+/// when a session appears in both locations.
+fn discover_sessions() -> anyhow::Result<Vec<DiscoveredSession>> {
+    let mut sessions: std::collections::BTreeMap<String, DiscoveredSession> =
+        std::collections::BTreeMap::new();
+
+    let roots = [Some(PathBuf::from("./logs")), backup_logs_root().ok()];
+    for root in roots.into_iter().flatten() {
+        let Ok(entries) = std::fs::read_dir(&root) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.starts_with("autonomous_session_") || !entry.path().is_dir() {
+                continue;
+            }
+            if sessions.contains_key(&name) {
+                continue;
+            }
+            let heartbeat_path = entry.path().join("heartbeat.json");
+            let Ok(content) = std::fs::read_to_string(&heartbeat_path) else {
+                continue;
+            };
+            let Ok(heartbeat) = serde_json::from_str::<serde_json::Value>(&content) else {
+                continue;
+            };
+            sessions.insert(
+                name.clone(),
+                DiscoveredSession {
+                    name,
+                    dir: entry.path(),
+                    heartbeat,
+                },
+            );
+        }
+    }
+
+    Ok(sessions.into_values().collect())
+}
+
+fn heartbeat_is_stale(heartbeat: &serde_json::Value, stale_after_secs: u64) -> bool {
+    let Some(timestamp) = heartbeat.get("timestamp").and_then(|t| t.as_str()) else {
+        return true;
+    };
+    match chrono::DateTime::parse_from_rfc3339(timestamp) {
+        Ok(ts) => {
+            let age = chrono::Utc::now().signed_duration_since(ts.with_timezone(&chrono::Utc));
+            age.num_seconds() > stale_after_secs as i64
+        }
+        Err(_) => true,
+    }
+}
+
+fn find_session(sessions: Vec<DiscoveredSession>, name: &str) -> anyhow::Result<DiscoveredSession> {
+    sessions
+        .into_iter()
+        .find(|s| s.name == name)
+        .ok_or_else(|| anyhow::anyhow!("No such autonomous session: {}", name))
+}
+
+/// Implements `codex autonomous manage list|status|stop|restart`, an
+/// operator-facing view over the heartbeat/session-info files that
+/// `run_autonomous_mode`'s `save_checkpoint` closure already writes.
+async fn run_autonomous_manage(cmd: AutonomousManageCommand) -> anyhow::Result<()> {
+    match cmd {
+        AutonomousManageCommand::List { stale_after_secs } => {
+            let sessions = discover_sessions()?;
+            if sessions.is_empty() {
+                println!("No autonomous sessions found under ./logs");
+                return Ok(());
+            }
+            for session in sessions {
+                let liveness = if heartbeat_is_stale(&session.heartbeat, stale_after_secs) {
+                    "stale"
+                } else {
+                    "alive"
+                };
+                println!(
+                    "{:<28} iter={:<5} elapsed={:>6}s model={:<20} pid={:<8} [{}]",
+                    session.name,
+                    session.heartbeat["iteration"].as_u64().unwrap_or(0),
+                    session.heartbeat["elapsed_seconds"].as_u64().unwrap_or(0),
+                    session.heartbeat["driver_model"].as_str().unwrap_or("?"),
+                    session.heartbeat["pid"].as_u64().unwrap_or(0),
+                    liveness
+                );
+            }
+        }
+        AutonomousManageCommand::Status {
+            session,
+            stale_after_secs,
+        } => {
+            let found = find_session(discover_sessions()?, &session)?;
+            let liveness = if heartbeat_is_stale(&found.heartbeat, stale_after_secs) {
+                "stale"
+            } else {
+                "alive"
+            };
+            println!("session:       {}", found.name);
+            println!("dir:           {:?}", found.dir);
+            println!(
+                "status:        {}",
+                found.heartbeat["status"].as_str().unwrap_or("unknown")
+            );
+            println!("liveness:      {}", liveness);
+            println!(
+                "iteration:     {}",
+                found.heartbeat["iteration"].as_u64().unwrap_or(0)
+            );
+            println!(
+                "elapsed:       {}s",
+                found.heartbeat["elapsed_seconds"].as_u64().unwrap_or(0)
+            );
+            println!(
+                "driver model:  {}",
+                found.heartbeat["driver_model"].as_str().unwrap_or("?")
+            );
+            println!(
+                "config file:   {}",
+                found.heartbeat["config_file"].as_str().unwrap_or("?")
+            );
+            println!(
+                "duration:      {} minutes",
+                found.heartbeat["duration_minutes"].as_u64().unwrap_or(0)
+            );
+            println!(
+                "full auto:     {}",
+                found.heartbeat["full_auto"].as_bool().unwrap_or(false)
+            );
+            println!("pid:           {}", found.heartbeat["pid"].as_u64().unwrap_or(0));
+            println!(
+                "last updated:  {}",
+                found.heartbeat["timestamp"].as_str().unwrap_or("?")
+            );
+        }
+        AutonomousManageCommand::Stop { session } => {
+            let found = find_session(discover_sessions()?, &session)?;
+            let pid = found.heartbeat["pid"].as_u64().ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Session {} has no recorded pid in its heartbeat; refusing to guess one",
+                    session
+                )
+            })?;
+            println!("🛑 Stopping session {} (pid {})", found.name, pid);
+            let status = std::process::Command::new("kill")
+                .arg(pid.to_string())
+                .status()
+                .with_context(|| format!("Failed to send SIGTERM to pid {}", pid))?;
+            if !status.success() {
+                return Err(anyhow::anyhow!(
+                    "kill exited with status {:?} for pid {}",
+                    status.code(),
+                    pid
+                ));
+            }
+            println!("✅ Sent SIGTERM to pid {}", pid);
+        }
+        AutonomousManageCommand::Restart {
+            session,
+            duration,
+            serve_token,
+        } => {
+            let found = find_session(discover_sessions()?, &session)?;
+            let config_file = found.heartbeat["config_file"]
+                .as_str()
+                .ok_or_else(|| anyhow::anyhow!("Session {} has no recorded config_file", session))?;
+            let driver_model = found.heartbeat["driver_model"].as_str().unwrap_or("o3");
+            let full_auto = found.heartbeat["full_auto"].as_bool().unwrap_or(false);
+            let profile = found.heartbeat["profile"].as_str();
+            let tool_confirmation_mode = found.heartbeat["tool_confirmation_mode"].as_str();
+            let max_context_tokens = found.heartbeat["max_context_tokens"].as_u64();
+            let serve = found.heartbeat["serve"].as_str();
+            let serve_token_was_set = found.heartbeat["serve_token_set"]
+                .as_bool()
+                .unwrap_or(false);
+
+            println!("🔄 Relaunching session {} from {:?}", found.name, found.dir);
+
+            let exe = std::env::current_exe().context("Failed to resolve current executable")?;
+            let mut command = std::process::Command::new(exe);
+            command
+                .arg("autonomous")
+                .arg("--config-file")
+                .arg(config_file)
+                .arg("--duration")
+                .arg(duration.to_string())
+                .arg("--driver-model")
+                .arg(driver_model)
+                .arg("--resume-dir")
+                .arg(&found.dir);
+            if full_auto {
+                command.arg("--full-auto");
+            }
+            if let Some(profile) = profile {
+                command.arg("--profile").arg(profile);
+            }
+            if let Some(tool_confirmation_mode) = tool_confirmation_mode {
+                command
+                    .arg("--tool-confirmation-mode")
+                    .arg(tool_confirmation_mode);
+            }
+            if let Some(max_context_tokens) = max_context_tokens {
+                command
+                    .arg("--max-context-tokens")
+                    .arg(max_context_tokens.to_string());
+            }
+            if let Some(serve) = serve {
+                command.arg("--serve").arg(serve);
+            }
+            if let Some(serve_token) = &serve_token {
+                command.arg("--serve-token").arg(serve_token);
+            } else if serve_token_was_set {
+                println!(
+                    "⚠️  Session {} was serving with a --serve-token set; the token itself is never \
+                     persisted to heartbeat.json, so pass `restart --serve-token <TOKEN>` if you \
+                     still want the tunnel locked down.",
+                    session
+                );
+            }
+
+            let status = command
+                .status()
+                .with_context(|| "Failed to relaunch autonomous session")?;
+            if !status.success() {
+                return Err(anyhow::anyhow!(
+                    "autonomous session exited with status {:?}",
+                    status.code()
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// One recorded iteration checkpoint, loaded from an `iteration_NNN.json`
+/// file the same way `save_checkpoint` in `run_autonomous_mode` writes it:
+/// the session's full `conversation_log` as of the end of that iteration.
+struct RecordedIteration {
+    number: u32,
+    conversation_log: Vec<serde_json::Value>,
+}
+
+/// Scan `session_dir` for `iteration_*.json` checkpoints and parse each,
+/// sorted by iteration number.
+fn load_recorded_iterations(
+    session_dir: &std::path::Path,
+) -> anyhow::Result<Vec<RecordedIteration>> {
+    let mut iterations = Vec::new();
+    let entries = std::fs::read_dir(session_dir)
+        .with_context(|| format!("Failed to read session directory: {:?}", session_dir))?;
+    for entry in entries.flatten() {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let Some(number_str) = file_name
+            .strip_prefix("iteration_")
+            .and_then(|s| s.strip_suffix(".json"))
+        else {
+            continue;
+        };
+        let number: u32 = number_str
+            .parse()
+            .with_context(|| format!("Unexpected checkpoint filename: {}", file_name))?;
+        let content = std::fs::read_to_string(entry.path())
+            .with_context(|| format!("Failed to read checkpoint: {:?}", entry.path()))?;
+        let conversation_log: Vec<serde_json::Value> = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse checkpoint: {:?}", entry.path()))?;
+        iterations.push(RecordedIteration {
+            number,
+            conversation_log,
+        });
+    }
+    iterations.sort_by_key(|it| it.number);
+    Ok(iterations)
+}
+
+/// One assistant-issued tool call paired with its recorded tool response, as
+/// extracted from a single iteration's `conversation_log`.
+struct RecordedToolCall {
+    tool_call_id: String,
+    tool_name: String,
+    arguments: serde_json::Value,
+    recorded_denied: bool,
+    recorded_reason: Option<String>,
+}
+
+/// A denied tool's recorded `content` is either a `"❌ ..."`-prefixed string
+/// (every built-in/plugin/MCP tool) or a `{"decision": "Denied", ...}`
+/// envelope (the `request_approval` tool's `ExecApprovalRequest` flow).
+fn tool_response_denied(content: &str) -> bool {
+    if content.starts_with('❌') {
+        return true;
+    }
+    serde_json::from_str::<serde_json::Value>(content)
+        .ok()
+        .and_then(|v| v.get("decision").and_then(|d| d.as_str()).map(str::to_lowercase))
+        .map(|d| d == "denied")
+        .unwrap_or(false)
+}
+
+/// Pair every assistant `tool_calls` entry in `conversation_log` with its
+/// `role: "tool"` response (if any), in the order the calls were issued.
+fn extract_recorded_tool_calls(conversation_log: &[serde_json::Value]) -> Vec<RecordedToolCall> {
+    let mut pending: std::collections::HashMap<String, (String, serde_json::Value)> =
+        std::collections::HashMap::new();
+    let mut order = Vec::new();
+    for message in conversation_log {
+        if let Some(tool_calls) = message.get("tool_calls").and_then(|v| v.as_array()) {
+            for call in tool_calls {
+                let Some(id) = call.get("id").and_then(|v| v.as_str()) else {
+                    continue;
+                };
+                let name = call["function"]["name"]
+                    .as_str()
+                    .unwrap_or("unknown")
+                    .to_string();
+                let arguments = call["function"]["arguments"]
+                    .as_str()
+                    .and_then(|s| serde_json::from_str(s).ok())
+                    .unwrap_or(serde_json::Value::Null);
+                pending.insert(id.to_string(), (name, arguments));
+                order.push(id.to_string());
+            }
+        }
+    }
+
+    let mut responses: std::collections::HashMap<String, (bool, Option<String>)> =
+        std::collections::HashMap::new();
+    for message in conversation_log {
+        if message.get("role").and_then(|r| r.as_str()) != Some("tool") {
+            continue;
+        }
+        let Some(id) = message.get("tool_call_id").and_then(|v| v.as_str()) else {
+            continue;
+        };
+        let content = message.get("content").and_then(|c| c.as_str()).unwrap_or("");
+        responses.insert(
+            id.to_string(),
+            (tool_response_denied(content), Some(content.to_string())),
+        );
+    }
+
+    order
+        .into_iter()
+        .filter_map(|id| {
+            let (name, arguments) = pending.remove(&id)?;
+            let (recorded_denied, recorded_reason) =
+                responses.get(&id).cloned().unwrap_or((false, None));
+            Some(RecordedToolCall {
+                tool_call_id: id,
+                tool_name: name,
+                arguments,
+                recorded_denied,
+                recorded_reason,
+            })
+        })
+        .collect()
+}
+
+/// Re-resolve today's tool policy for a recorded call the same way the live
+/// loop would: `request_approval` calls (raw shell commands surfaced via
+/// `ExecApprovalRequest`) go through [`resolve_command_tool_policy`] against
+/// their recorded `command` argument; every other tool name goes through
+/// [`resolve_tool_policy`].
+fn resolve_recorded_tool_policy(
+    policy_table: &std::collections::HashMap<String, ToolPolicyAction>,
+    tool_name: &str,
+    arguments: &serde_json::Value,
+) -> ToolPolicyAction {
+    if tool_name == "request_approval" {
+        let command: Vec<String> = arguments
+            .get("command")
+            .and_then(|v| v.as_array())
+            .map(|arr| {
+                arr.iter()
+                    .filter_map(|item| item.as_str().map(|s| s.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        resolve_command_tool_policy(policy_table, &command)
+    } else {
+        resolve_tool_policy(policy_table, tool_name)
+    }
+}
+
+/// Per-tool-call regression check: whether re-resolving this call's policy
+/// against the current config would change its fate in a way that's
+/// actually deterministic (a newly-introduced `Deny`, or a `Deny` that no
+/// longer applies). A call that was routed to `RequireLlmApproval` and then
+/// approved or denied by the external LLM is never flagged either way,
+/// since that outcome isn't reproducible without re-running the model.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct ReplayToolCallCheck {
+    tool_call_id: String,
+    tool_name: String,
+    recorded_denied: bool,
+    current_policy: String,
+    regressed: bool,
+}
+
+/// Tool-call policy checks for one recorded iteration.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct ReplayIterationReport {
+    iteration: u32,
+    tool_calls: Vec<ReplayToolCallCheck>,
+}
+
+/// Whether the deterministic compaction trigger (`compute_compaction_plan`)
+/// still agrees with what's recorded: given the conversation log as it
+/// stood at the end of one iteration, would compaction fire before the
+/// next one starts, and did it actually fire in the recording?
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct ReplayCompactionCheck {
+    before_iteration: u32,
+    predicted_compaction: bool,
+    recorded_compaction: bool,
+    regressed: bool,
+}
+
+/// The full structured pass/fail report for one `codex replay` run, also
+/// serialized as-is into `replay_snapshot.json`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct ReplayReport {
+    session_dir: String,
+    iterations: Vec<ReplayIterationReport>,
+    compaction_checks: Vec<ReplayCompactionCheck>,
+    context_matches: bool,
+}
+
+/// Implements `codex replay`: re-check a recorded autonomous session's
+/// tool-policy routing, compaction triggers, and final context rendering
+/// against the current config, using only what `run_autonomous_mode`
+/// already persisted to `session_dir` — no network calls, no re-running the
+/// driver model. Approval/denial outcomes that came from the external LLM
+/// are read back but never asserted against, since they aren't
+/// reproducible offline; only the deterministic parts of the pipeline
+/// (tool-policy routing, compaction thresholds, context rendering) are
+/// checked. With `--update-snapshot` the freshly computed report becomes
+/// the new `replay_snapshot.json` baseline; otherwise any regression found
+/// in this run fails the command.
+async fn run_replay(cmd: ReplayCommand) -> anyhow::Result<()> {
+    let iterations = load_recorded_iterations(&cmd.session_dir)?;
+    if iterations.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No iteration_*.json checkpoints found under {:?}",
+            cmd.session_dir
+        ));
+    }
+
+    let config_file = match &cmd.config_file {
+        Some(path) => path.clone(),
+        None => {
+            let heartbeat_path = cmd.session_dir.join("heartbeat.json");
+            let heartbeat_content = std::fs::read_to_string(&heartbeat_path).with_context(|| {
+                format!(
+                    "No --config-file given and failed to read {:?} to find one",
+                    heartbeat_path
+                )
+            })?;
+            let heartbeat: serde_json::Value = serde_json::from_str(&heartbeat_content)
+                .with_context(|| format!("Failed to parse {:?}", heartbeat_path))?;
+            heartbeat["config_file"]
+                .as_str()
+                .map(PathBuf::from)
+                .ok_or_else(|| anyhow::anyhow!("{:?} has no recorded config_file", heartbeat_path))?
+        }
+    };
+    let config_content = std::fs::read_to_string(&config_file)
+        .with_context(|| format!("Failed to read config file: {:?}", config_file))?;
+    let tool_policy_table = load_tool_policy_table(&config_content)?;
+
+    // Check compaction thresholds against the `max_context_tokens` recorded in
+    // the session's `heartbeat.json` (the value the run last used), falling
+    // back to the same hardcoded default `run_autonomous_mode` uses absent a
+    // profile or flag when the heartbeat is missing or has no such field.
+    let max_context_tokens = std::fs::read_to_string(cmd.session_dir.join("heartbeat.json"))
+        .ok()
+        .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok())
+        .and_then(|heartbeat| heartbeat["max_context_tokens"].as_u64())
+        .map(|value| value as usize)
+        .unwrap_or(200_000);
+
+    let mut iteration_reports = Vec::new();
+    for iteration in &iterations {
+        let tool_calls = extract_recorded_tool_calls(&iteration.conversation_log)
+            .into_iter()
+            .map(|call| {
+                let current_policy = resolve_recorded_tool_policy(
+                    &tool_policy_table,
+                    &call.tool_name,
+                    &call.arguments,
+                );
+                let was_policy_denied = call.recorded_denied
+                    && call
+                        .recorded_reason
+                        .as_deref()
+                        .map(|r| r.contains("tool policy"))
+                        .unwrap_or(false);
+                let regressed = match current_policy {
+                    ToolPolicyAction::Deny => !call.recorded_denied,
+                    _ => was_policy_denied,
+                };
+                ReplayToolCallCheck {
+                    tool_call_id: call.tool_call_id,
+                    tool_name: call.tool_name,
+                    recorded_denied: call.recorded_denied,
+                    current_policy: format!("{:?}", current_policy),
+                    regressed,
                 }
+            })
+            .collect();
+        iteration_reports.push(ReplayIterationReport {
+            iteration: iteration.number,
+            tool_calls,
+        });
+    }
+
+    let mut compaction_checks = Vec::new();
+    for window in iterations.windows(2) {
+        let (prev, next) = (&window[0], &window[1]);
+        let plan = compute_compaction_plan(&prev.conversation_log, max_context_tokens)?;
+        let predicted_compaction = plan.is_some();
+        let recorded_compaction = plan
+            .as_ref()
+            .and_then(|p| next.conversation_log.get(p.system_end))
+            .map(|msg| {
+                msg.get("role").and_then(|r| r.as_str()) == Some("system")
+                    && msg.get("compacted").and_then(|c| c.as_bool()) == Some(true)
+            })
+            .unwrap_or(false);
+        compaction_checks.push(ReplayCompactionCheck {
+            before_iteration: next.number,
+            predicted_compaction,
+            recorded_compaction,
+            regressed: predicted_compaction != recorded_compaction,
+        });
+    }
+
+    let final_log = &iterations
+        .last()
+        .ok_or_else(|| anyhow::anyhow!("No iterations to replay"))?
+        .conversation_log;
+    let recomputed_context = build_readable_context(final_log);
+    let context_log_path = cmd.session_dir.join("context_log.txt");
+    let recorded_context = std::fs::read_to_string(&context_log_path).unwrap_or_default();
+    let context_matches = recomputed_context == recorded_context;
+
+    let report = ReplayReport {
+        session_dir: cmd.session_dir.to_string_lossy().to_string(),
+        iterations: iteration_reports,
+        compaction_checks,
+        context_matches,
+    };
 
-                // Generate follow-up prompt from supervisor with tool results
-                let follow_up_context = format!("{}\n\nTool Results:\n{}",
-                    final_context,
-                    serde_json::to_string_pretty(&tool_results).unwrap_or_default()
+    let snapshot_path = cmd.session_dir.join("replay_snapshot.json");
+    if cmd.update_snapshot {
+        std::fs::write(&snapshot_path, serde_json::to_string_pretty(&report)?)
+            .with_context(|| format!("Failed to write snapshot: {:?}", snapshot_path))?;
+        println!("📸 Wrote replay snapshot to {:?}", snapshot_path);
+        return Ok(());
+    }
+
+    if let Ok(previous_content) = std::fs::read_to_string(&snapshot_path) {
+        if let Ok(previous) = serde_json::from_str::<ReplayReport>(&previous_content) {
+            if previous.iterations.len() != report.iterations.len() {
+                println!(
+                    "⚠️  Recorded session now has {} iteration(s), snapshot had {}",
+                    report.iterations.len(),
+                    previous.iterations.len()
                 );
+            }
+        }
+    }
 
-                let follow_up_driver_prompt = inject_template_variables(
-                    &continuation_prompt_template,
-                    &config_content,
-                    &follow_up_context,
+    let mut any_regressed = false;
+    for iteration_report in &report.iterations {
+        for check in &iteration_report.tool_calls {
+            if check.regressed {
+                any_regressed = true;
+                println!(
+                    "❌ iteration {}: tool '{}' ({}) now resolves to {} — policy routing regression",
+                    iteration_report.iteration,
+                    check.tool_name,
+                    check.tool_call_id,
+                    check.current_policy
                 );
+            }
+        }
+    }
+    for check in &report.compaction_checks {
+        if check.regressed {
+            any_regressed = true;
+            println!(
+                "❌ before iteration {}: compaction predicted={} recorded={} — threshold regression",
+                check.before_iteration, check.predicted_compaction, check.recorded_compaction
+            );
+        }
+    }
+    if !report.context_matches {
+        any_regressed = true;
+        println!("❌ build_readable_context(final conversation_log) no longer matches context_log.txt");
+    }
 
-                let (follow_up_prompt, _) = generate_user_prompt(
-                    &follow_up_driver_prompt,
-                    &autonomous_cli.driver_model,
-                    &session_logs_dir,
-                ).await?;
+    if any_regressed {
+        Err(anyhow::anyhow!(
+            "Replay of {:?} found regressions",
+            cmd.session_dir
+        ))
+    } else {
+        println!(
+            "✅ Replay of {:?} found no regressions across {} iteration(s)",
+            cmd.session_dir,
+            report.iterations.len()
+        );
+        Ok(())
+    }
+}
 
-                println!("🔄 Supervisor follow-up prompt: {}", follow_up_prompt);
+/// One recorded approval scenario in an approval-replay fixture file: the
+/// exec request that was seen plus the decision it's expected to resolve
+/// to, so `run_approval_replay` can assert the same inputs still resolve
+/// the same way without calling a live LLM.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct ApprovalFixtureScenario {
+    name: String,
+    #[serde(default)]
+    command: Vec<String>,
+    #[serde(default)]
+    cwd: String,
+    /// Canned text standing in for what the external LLM would have
+    /// replied, fed straight to `parse_approval_response` whenever neither
+    /// an approval hook nor the tool policy table decides on its own —
+    /// this is what makes the scenario replayable offline.
+    #[serde(default)]
+    stubbed_llm_response: Option<String>,
+    /// Expected outcome: `"approved"` or `"denied"`.
+    expected_decision: String,
+}
 
-                // Add follow-up user message to conversation log
-                conversation_log.push(serde_json::json!({
-                    "role": "user",
-                    "content": follow_up_prompt
-                }));
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct ApprovalFixtureFile {
+    #[serde(default)]
+    scenarios: Vec<ApprovalFixtureScenario>,
+}
 
-                // Update context with follow-up conversation
-                final_context = format!("{}\n\nUSER: {}\n\nASSISTANT: {}",
-                    final_context, follow_up_prompt, follow_up_prompt);
+/// Load the scenario list from a JSON approval-replay fixture file.
+fn load_approval_fixtures(path: &std::path::Path) -> anyhow::Result<Vec<ApprovalFixtureScenario>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read approval fixture file: {:?}", path))?;
+    let parsed: ApprovalFixtureFile = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse approval fixture file: {:?}", path))?;
+    Ok(parsed.scenarios)
+}
 
-                follow_up_prompt
-            } else {
-                // Case 1: No tool calls - use original supervisor message directly
+/// Resolve one fixture scenario's decision offline: `approval_hooks` get
+/// first say exactly as in `collect_codex_response_with_tools`'s
+/// `ExecApprovalRequest` handling, then the tool policy table, falling back
+/// to the scenario's `stubbed_llm_response` fed through
+/// `parse_approval_response` wherever the live pipeline would have asked an
+/// external LLM.
+fn replay_approval_scenario(
+    scenario: &ApprovalFixtureScenario,
+    approval_hooks: &[ApprovalHookConfig],
+    tool_policy_table: &std::collections::HashMap<String, ToolPolicyAction>,
+) -> anyhow::Result<bool> {
+    let cwd = std::path::Path::new(&scenario.cwd);
+    let hook_decision = evaluate_exec_approval_hooks(approval_hooks, &scenario.command, cwd)?;
+
+    match &hook_decision {
+        Some((_, ApprovalHookOutcome::Allow)) => return Ok(true),
+        Some((_, ApprovalHookOutcome::Deny)) => return Ok(false),
+        Some((_, ApprovalHookOutcome::Defer)) => {
+            unreachable!("evaluate_exec_approval_hooks never returns Defer")
+        }
+        Some((_, ApprovalHookOutcome::Llm)) => {}
+        None => match resolve_command_tool_policy(tool_policy_table, &scenario.command) {
+            ToolPolicyAction::AutoApprove => return Ok(true),
+            ToolPolicyAction::Deny => return Ok(false),
+            ToolPolicyAction::RequireLlmApproval => {}
+        },
+    }
 
-                // Add regular user message to conversation log
-                conversation_log.push(serde_json::json!({
-                    "role": "user",
-                    "content": user_prompt
-                }));
+    let stubbed_response = scenario.stubbed_llm_response.as_deref().ok_or_else(|| {
+        anyhow::anyhow!(
+            "Scenario '{}' resolves to an LLM decision but has no stubbed_llm_response to replay",
+            scenario.name
+        )
+    })?;
+    let (approved, _reasoning) = parse_approval_response(stubbed_response);
+    Ok(approved)
+}
 
-                user_prompt
-            };
+/// Implements `codex approval-replay`: run every scenario in a fixture file
+/// through the same approval_hooks -> tool_policy_table -> (stubbed) LLM
+/// pipeline `collect_codex_response_with_tools` uses for
+/// `ExecApprovalRequest`, reporting which scenarios' decisions still match
+/// what's recorded. With `--watch`, re-runs the suite whenever the fixture
+/// or config file changes instead of exiting after one pass.
+async fn run_approval_replay(cmd: ApprovalReplayCommand) -> anyhow::Result<()> {
+    if cmd.watch {
+        let mut rx = spawn_file_watcher(vec![cmd.fixtures_file.clone(), cmd.config_file.clone()]);
+        if let Err(e) = run_approval_replay_once(&cmd) {
+            println!("❌ {}", e);
+        }
+        while rx.recv().await.is_some() {
+            println!("\n👀 Fixture or config change detected, re-running approval replay...");
+            if let Err(e) = run_approval_replay_once(&cmd) {
+                println!("❌ {}", e);
+            }
+        }
+        return Ok(());
+    }
 
-            // Submit to codex
-            let input_items = vec![InputItem::Text {
-                text: final_user_prompt.clone(),
-            }];
-            let submission_id: String = codex.submit(Op::UserInput { items: input_items }).await?;
+    run_approval_replay_once(&cmd)
+}
 
-            // Collect codex response and tool calls
-            let (codex_response, tool_calls, reasoning, tool_responses) =
-                collect_codex_response_with_tools(
-                    &codex,
-                    &submission_id,
-                    autonomous_cli.full_auto,
-                    &autonomous_cli.driver_model,
-                    &approval_prompt_template,
-                    &bugcrowd_approval_prompt_template,
-                    &session_logs_dir,
-                    &config_content,
-                )
-                .await?;
+fn run_approval_replay_once(cmd: &ApprovalReplayCommand) -> anyhow::Result<()> {
+    let scenarios = load_approval_fixtures(&cmd.fixtures_file)?;
+    let config_content = std::fs::read_to_string(&cmd.config_file)
+        .with_context(|| format!("Failed to read config file: {:?}", cmd.config_file))?;
+    let tool_policy_table = load_tool_policy_table(&config_content)?;
+    let approval_hooks = load_approval_hooks(&config_content)?;
+
+    let mut failures = 0;
+    for scenario in &scenarios {
+        match replay_approval_scenario(scenario, &approval_hooks, &tool_policy_table) {
+            Ok(approved) => {
+                let decision = if approved { "approved" } else { "denied" };
+                if decision == scenario.expected_decision {
+                    println!("✅ {} -> {}", scenario.name, decision);
+                } else {
+                    failures += 1;
+                    println!(
+                        "❌ {} -> {} (expected {})",
+                        scenario.name, decision, scenario.expected_decision
+                    );
+                }
+            }
+            Err(e) => {
+                failures += 1;
+                println!("❌ {} -> error: {}", scenario.name, e);
+            }
+        }
+    }
 
-            println!("🤖 Codex response collected");
+    if failures > 0 {
+        Err(anyhow::anyhow!(
+            "{} of {} approval scenario(s) failed",
+            failures,
+            scenarios.len()
+        ))
+    } else {
+        println!("✅ All {} approval scenario(s) passed", scenarios.len());
+        Ok(())
+    }
+}
 
-            // Add events in correct chronological order:
+/// One `codex bench` workload file: seeds a supervisor session with
+/// `user_message` and tracks it against an optional `max_steps` budget.
+/// `target`/`asset` is carried through to the report purely as a label (e.g.
+/// the asset the session was meant to test) and isn't otherwise interpreted.
+#[derive(Debug, Clone, serde::Deserialize)]
+struct BenchWorkload {
+    name: String,
+    #[serde(default, alias = "asset")]
+    target: Option<String>,
+    user_message: String,
+    max_steps: Option<u32>,
+    #[serde(default)]
+    env: std::collections::HashMap<String, String>,
+}
 
-            // 1. Assistant reasoning (if present)
-            if let Some(reasoning_text) = reasoning {
-                conversation_log.push(serde_json::json!({
-                    "role": "assistant",
-                    "content": "",
-                    "reasoning": reasoning_text
-                }));
-            }
+/// Aggregate metrics for one workload's run, as reported by `codex bench`.
+#[derive(Debug, Clone, serde::Serialize)]
+struct BenchResult {
+    name: String,
+    target: Option<String>,
+    wall_clock_secs: f64,
+    llm_round_trips: usize,
+    tool_call_counts: std::collections::HashMap<String, usize>,
+    finished: bool,
+    exceeded_max_steps: bool,
+    error: Option<String>,
+}
 
-            // 2. Assistant tool calls (if any)
-            if !tool_calls.is_empty() {
-                conversation_log.push(serde_json::json!({
-                    "role": "assistant",
-                    "content": "",
-                    "tool_calls": tool_calls
-                }));
-            }
+/// Load one `codex bench` workload file (see [`BenchWorkload`]).
+fn load_bench_workload(path: &std::path::Path) -> anyhow::Result<BenchWorkload> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read workload file: {:?}", path))?;
+    serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse workload file: {:?}", path))
+}
 
-            // 3. Tool responses
-            for tool_response in tool_responses {
-                conversation_log.push(tool_response);
-            }
+/// Implements `codex bench`: run each `--workloads` file's `user_message`
+/// through the same `run_driver_tool_loop` an autonomous session drives every
+/// iteration through, measuring wall-clock time, LLM round trips (one per
+/// `generate_driver_response` call), tool-call counts by name, and whether
+/// the `finished` tool fired, so sessions can be regression-tracked across
+/// model/prompt changes. Each workload runs against its own fresh
+/// `PluginRegistry` and logs directory so one workload's plugin state or
+/// notes can't leak into another's.
+async fn run_bench(cmd: BenchCommand) -> anyhow::Result<()> {
+    let config_content = std::fs::read_to_string(&cmd.config_file)
+        .with_context(|| format!("Failed to read config file: {:?}", cmd.config_file))?;
+    let tool_policy_table = load_tool_policy_table(&config_content)?;
+    let hook_table = load_hook_table(&config_content)?;
 
-            // 4. Final assistant response
-            conversation_log.push(serde_json::json!({
-                "role": "assistant",
-                "content": codex_response
-            }));
+    let core_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap()
+        .join("core");
+    let bugcrowd_approval_prompt_file = core_dir.join("bugcrowd_approval_prompt.txt");
+    let bugcrowd_approval_prompt_template = std::fs::read_to_string(&bugcrowd_approval_prompt_file)
+        .with_context(|| {
+            format!(
+                "Failed to read bugcrowd approval prompt file: {:?}",
+                bugcrowd_approval_prompt_file
+            )
+        })?;
 
-            // Build readable conversation context
-            let mut readable_context = String::new();
-            for msg in &conversation_log {
-                match msg.get("role").and_then(|r| r.as_str()) {
-                    Some("system") => {
-                        readable_context.push_str(&format!(
-                            "SYSTEM: {}\n\n",
-                            msg.get("content").and_then(|c| c.as_str()).unwrap_or("")
-                        ));
-                    }
-                    Some("user") => {
-                        readable_context.push_str(&format!(
-                            "USER: {}\n\n",
-                            msg.get("content").and_then(|c| c.as_str()).unwrap_or("")
-                        ));
-                    }
-                    Some("assistant") => {
-                        if let Some(reasoning) = msg.get("reasoning") {
-                            readable_context.push_str(&format!(
-                                "ASSISTANT_REASONING: {}\n\n",
-                                reasoning.as_str().unwrap_or("")
-                            ));
-                        } else if let Some(tool_calls) = msg.get("tool_calls") {
-                            // Filter out system tool calls
-                            let empty_vec = vec![];
-                            let tool_calls_array = tool_calls.as_array().unwrap_or(&empty_vec);
-                            let filtered_tool_calls: Vec<_> = tool_calls_array
-                                .iter()
-                                .filter(|tool_call| {
-                                    tool_call.get("type").and_then(|t| t.as_str()) != Some("system")
-                                })
-                                .collect();
+    let driver_model = cmd.driver_model.clone().unwrap_or_else(|| "o3".to_string());
+    let remote_approval_timeout = std::time::Duration::from_secs(30);
+    let bench_logs_root = PathBuf::from("bench_logs");
+
+    let mut results = Vec::new();
+    for workload_path in &cmd.workloads {
+        let workload = load_bench_workload(workload_path)?;
+        println!("🏋️  Running workload '{}'...", workload.name);
+
+        // Apply the workload's env overrides only for the duration of its
+        // own run, restoring whatever was there before (or unsetting it)
+        // once this workload finishes.
+        let mut previous_env = Vec::new();
+        for (key, value) in &workload.env {
+            previous_env.push((key.clone(), std::env::var(key).ok()));
+            std::env::set_var(key, value);
+        }
 
-                            if !filtered_tool_calls.is_empty() {
-                                readable_context.push_str(&format!(
-                                    "ASSISTANT_TOOL_CALLS: {}\n\n",
-                                    serde_json::to_string_pretty(&filtered_tool_calls).unwrap_or_default()
-                                ));
-                            }
-                        } else {
-                            readable_context.push_str(&format!(
-                                "ASSISTANT: {}\n\n",
-                                msg.get("content").and_then(|c| c.as_str()).unwrap_or("")
-                            ));
-                        }
-                    }
-                    Some("tool") => {
-                        readable_context.push_str(&format!(
-                            "TOOL_RESPONSE: {}\n\n",
-                            msg.get("content").and_then(|c| c.as_str()).unwrap_or("")
-                        ));
-                    }
-                    _ => {
-                        // Skip unknown roles
-                    }
-                }
-            }
+        let session_logs_dir = bench_logs_root.join(&workload.name);
+        std::fs::create_dir_all(&session_logs_dir).with_context(|| {
+            format!("Failed to create bench logs directory: {:?}", session_logs_dir)
+        })?;
 
-            // Use summarized context if we summarized this iteration, otherwise use rebuilt context
-            if context_was_summarized {
-                context = final_context;
-            } else {
-                context = readable_context;
-            }
+        let mut plugin_registry = PluginRegistry::spawn_from_config(&config_content).await?;
+        let mut conversation_log = Vec::new();
+        let mut tool_cache = std::collections::HashMap::new();
+
+        let start = std::time::Instant::now();
+        let run_result = run_driver_tool_loop(
+            &workload.user_message,
+            &driver_model,
+            &session_logs_dir,
+            &config_content,
+            &mut conversation_log,
+            &mut tool_cache,
+            &mut plugin_registry,
+            &tool_policy_table,
+            &bugcrowd_approval_prompt_template,
+            &hook_table,
+            None,
+            remote_approval_timeout,
+            ToolConfirmationMode::Auto,
+        )
+        .await;
+        let wall_clock_secs = start.elapsed().as_secs_f64();
 
-            // Save context string to file for testing
-            let context_log_path = session_logs_dir.join("context_log.txt");
-            if let Err(e) = std::fs::write(&context_log_path, &context) {
-                eprintln!("❌ Failed to save context log: {}", e);
+        for (key, previous) in previous_env {
+            match previous {
+                Some(value) => std::env::set_var(&key, value),
+                None => std::env::remove_var(&key),
             }
+        }
 
-            // Save checkpoint after each iteration
-            save_checkpoint(&conversation_log, iteration as u32);
+        let (finished, error) = match &run_result {
+            Ok((_, finished)) => (*finished, None),
+            Err(e) => {
+                let last_tool_call = conversation_log
+                    .iter()
+                    .rev()
+                    .find_map(|entry| entry.get("tool_calls").and_then(|v| v.as_array()))
+                    .and_then(|tool_calls| tool_calls.last())
+                    .and_then(|tool_call| tool_call["function"]["name"].as_str());
+                report_session_crash(
+                    e,
+                    &session_logs_dir,
+                    Some(&workload.name),
+                    Some(conversation_log.iter().filter(|entry| entry["role"] == "user").count() as u32),
+                    last_tool_call,
+                )
+                .await;
+                (false, Some(e.to_string()))
+            }
+        };
 
+        // Every step of `run_driver_tool_loop` pushes exactly one
+        // `{"role": "user", ...}` entry (with or without "tool_calls"), so
+        // counting those is the same as counting LLM round trips.
+        let llm_round_trips = conversation_log
+            .iter()
+            .filter(|entry| entry["role"] == "user")
+            .count();
+        let mut tool_call_counts: std::collections::HashMap<String, usize> =
+            std::collections::HashMap::new();
+        for entry in &conversation_log {
+            if let Some(tool_calls) = entry.get("tool_calls").and_then(|v| v.as_array()) {
+                for tool_call in tool_calls {
+                    let name = tool_call["function"]["name"]
+                        .as_str()
+                        .unwrap_or("unknown")
+                        .to_string();
+                    *tool_call_counts.entry(name).or_insert(0) += 1;
+                }
+            }
+        }
+        let exceeded_max_steps = workload
+            .max_steps
+            .map(|max_steps| llm_round_trips as u32 > max_steps)
+            .unwrap_or(false);
 
-            // Wait before next iteration
-            sleep(Duration::from_secs(10)).await;
+        if let Some(e) = &error {
+            println!("❌ Workload '{}' failed: {}", workload.name, e);
+        } else {
+            println!(
+                "✅ Workload '{}' finished={} in {:.2}s across {} round trip(s)",
+                workload.name, finished, wall_clock_secs, llm_round_trips
+            );
         }
 
-        println!(
-            "✅ Autonomous mode completed after {} iterations",
-            iteration
-        );
-        Ok::<(), anyhow::Error>(())
+        results.push(BenchResult {
+            name: workload.name,
+            target: workload.target,
+            wall_clock_secs,
+            llm_round_trips,
+            tool_call_counts,
+            finished,
+            exceeded_max_steps,
+            error,
+        });
     }
-    .await;
-
-    // Save final checkpoint regardless of how we exit
-    save_checkpoint(&conversation_log, iteration as u32);
-
-    // Update final heartbeat with completion status
-    let final_status = if loop_result.is_ok() {
-        "completed"
-    } else {
-        "error"
-    };
-    let final_heartbeat = serde_json::json!({
-        "timestamp": chrono::Utc::now().to_rfc3339(),
-        "iteration": iteration,
-        "session_timestamp": session_timestamp,
-        "elapsed_seconds": start_time.elapsed().as_secs(),
-        "status": final_status,
-        "pid": std::process::id(),
-        "config_file": autonomous_cli.config_file.to_string_lossy(),
-        "duration_minutes": autonomous_cli.duration,
-        "driver_model": &autonomous_cli.driver_model,
-        "full_auto": autonomous_cli.full_auto
-    });
 
-    let final_heartbeat_json = serde_json::to_string_pretty(&final_heartbeat).unwrap_or_default();
-    let global_heartbeat_path = PathBuf::from("./logs/latest_session_heartbeat.json");
-    if let Err(e) = std::fs::write(&global_heartbeat_path, &final_heartbeat_json) {
-        eprintln!("❌ Failed to save final heartbeat: {}", e);
+    let report_json = serde_json::to_string_pretty(&results)?;
+    match &cmd.report_out {
+        Some(path) => {
+            std::fs::write(path, &report_json)
+                .with_context(|| format!("Failed to write bench report: {:?}", path))?;
+            println!("📄 Wrote bench report to {:?}", path);
+        }
+        None => println!("{}", report_json),
     }
 
-    println!(
-        "🏁 Final checkpoint saved for session {}",
-        session_timestamp
-    );
+    if let Some(dashboard_url) = &cmd.dashboard_url {
+        let api_key = cmd
+            .dashboard_api_key
+            .clone()
+            .or_else(|| std::env::var("BENCH_DASHBOARD_API_KEY").ok());
+        let mut curl_args = vec![
+            "-X".to_string(),
+            "POST".to_string(),
+            "-H".to_string(),
+            "Content-Type: application/json".to_string(),
+        ];
+        if let Some(api_key) = &api_key {
+            curl_args.push("-H".to_string());
+            curl_args.push(format!("Authorization: Bearer {}", api_key));
+        }
+        curl_args.push("--data".to_string());
+        curl_args.push(report_json.clone());
+        curl_args.push(dashboard_url.clone());
+
+        match std::process::Command::new("curl").args(&curl_args).output() {
+            Ok(output) => {
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                println!(
+                    "📡 Posted bench report to dashboard: stdout={}, stderr={}",
+                    stdout, stderr
+                );
+            }
+            Err(e) => {
+                println!("❌ Failed to post bench report to dashboard: {}", e);
+            }
+        }
+    }
 
-    // Return the result
-    loop_result
+    Ok(())
 }
 
 async fn collect_codex_response_with_tools(
@@ -978,6 +3712,13 @@ async fn collect_codex_response_with_tools(
     bugcrowd_approval_prompt_template: &str,
     session_logs_dir: &std::path::Path,
     config_content: &str,
+    tool_policy_table: &std::collections::HashMap<String, ToolPolicyAction>,
+    stream_tx: &tokio::sync::mpsc::UnboundedSender<StreamChunk>,
+    hook_table: &std::collections::HashMap<String, Vec<HookConfig>>,
+    observability_hub: Option<&ObservabilityHub>,
+    approval_hooks: &[ApprovalHookConfig],
+    model_provider: &codex_core::model_provider_info::ModelProviderInfo,
+    remote_approval_timeout: std::time::Duration,
 ) -> anyhow::Result<(
     String,
     Vec<serde_json::Value>,
@@ -991,20 +3732,29 @@ async fn collect_codex_response_with_tools(
     let mut tool_responses = Vec::new();
     let mut task_complete = false;
     let mut denied_tool_calls = std::collections::HashSet::new();
+    let mut tool_args_buffer = ToolArgsBuffer::default();
+    // Consecutive transient stream errors seen since the last successful
+    // receive; reset to 0 whenever an event comes through cleanly.
+    let mut stream_retry_count: u64 = 0;
 
     // Collect events until task is complete
     while !task_complete {
         match codex.next_event().await {
             Ok(event) => {
+                stream_retry_count = 0;
                 if event.id == submission_id {
                     match event.msg {
                         EventMsg::AgentMessage(msg) => {
-                            println!("🤖 Agent: {}", msg.message);
+                            let _ = stream_tx.send(StreamChunk::TextDelta {
+                                text: msg.message.clone(),
+                            });
                             assistant_content.push_str(&msg.message);
                             assistant_content.push('\n');
                         }
                         EventMsg::AgentReasoning(reasoning) => {
-                            println!("🧠 Reasoning: {}", reasoning.text);
+                            let _ = stream_tx.send(StreamChunk::ReasoningDelta {
+                                text: reasoning.text.clone(),
+                            });
                             reasoning_content.push_str(&reasoning.text);
                             reasoning_content.push('\n');
                         }
@@ -1045,74 +3795,187 @@ async fn collect_codex_response_with_tools(
                         EventMsg::McpToolCallBegin(tool) => {
                             println!("🔧 Calling tool: {}", tool.invocation.tool);
 
-                            // Check if this is a bugcrowd_submit call - always require external LLM approval
-                            if tool.invocation.tool == "bugcrowd_submit" {
+                            // `McpToolCallBegin` delivers arguments already
+                            // parsed rather than as streamed JSON fragments,
+                            // so buffering degenerates to a single fragment
+                            // here — but it runs through the same
+                            // accumulate/finalize path a wire protocol that
+                            // streams partial tool-call JSON would need.
+                            let partial_json = tool
+                                .invocation
+                                .arguments
+                                .as_ref()
+                                .map(|args| args.to_string())
+                                .unwrap_or_else(|| "{}".to_string());
+                            tool_args_buffer.push(&tool.call_id, &partial_json);
+                            let _ = stream_tx.send(StreamChunk::ToolArgsDelta {
+                                call_id: tool.call_id.clone(),
+                                partial_json,
+                            });
+                            match tool_args_buffer.finalize(&tool.call_id) {
+                                Ok(arguments) => {
+                                    let _ = stream_tx.send(StreamChunk::ToolArgsComplete {
+                                        call_id: tool.call_id.clone(),
+                                        name: tool.invocation.tool.clone(),
+                                        arguments,
+                                    });
+                                }
+                                Err(e) => {
+                                    println!(
+                                        "⚠️ Failed to finalize buffered arguments for '{}': {}",
+                                        tool.invocation.tool, e
+                                    );
+                                }
+                            }
+
+                            let vetoed = run_lifecycle_hooks(
+                                hook_table,
+                                "tool_call_begin",
+                                &serde_json::json!({
+                                    "tool": tool.invocation.tool,
+                                    "arguments": tool.invocation.arguments,
+                                }),
+                                session_logs_dir,
+                            );
+                            if vetoed {
                                 println!(
-                                    "🤖 Requesting approval from external LLM for bugcrowd_submit tool..."
+                                    "🚫 Denying '{}' per tool_call_begin hook veto",
+                                    tool.invocation.tool
                                 );
-
-                                // Use the specialized bugcrowd approval prompt
-                                let tool_approval_prompt = inject_bugcrowd_approval_variables(
-                                    bugcrowd_approval_prompt_template,
-                                    &tool.invocation.tool,
-                                    &tool.invocation.arguments,
+                                run_lifecycle_hooks(
+                                    hook_table,
+                                    "tool_denied",
+                                    &serde_json::json!({
+                                        "tool": tool.invocation.tool,
+                                        "reason": "vetoed by tool_call_begin hook",
+                                    }),
+                                    session_logs_dir,
                                 );
+                                denied_tool_calls.insert(tool.call_id.clone());
+                                tool_responses.push(serde_json::json!({
+                                    "role": "tool",
+                                    "tool_call_id": tool.call_id,
+                                    "content": format!("❌ '{}' denied by lifecycle hook", tool.invocation.tool)
+                                }));
+                                continue;
+                            }
 
-                                match generate_user_prompt(
-                                    &tool_approval_prompt,
-                                    driver_model,
-                                    &session_logs_dir,
-                                )
-                                .await
-                                {
-                                    Ok((response, _)) => {
-                                        println!("🤖 External LLM response: {}", response);
-                                        let (approved, reasoning) =
-                                            parse_approval_response(&response);
+                            match resolve_tool_policy(tool_policy_table, &tool.invocation.tool) {
+                                ToolPolicyAction::AutoApprove => {
+                                    // Read-only / whitelisted tool: let it proceed without review.
+                                }
+                                ToolPolicyAction::Deny => {
+                                    println!(
+                                        "🚫 Denying '{}' per tool policy (no LLM consulted)",
+                                        tool.invocation.tool
+                                    );
+                                    run_lifecycle_hooks(
+                                        hook_table,
+                                        "tool_denied",
+                                        &serde_json::json!({
+                                            "tool": tool.invocation.tool,
+                                            "reason": "tool policy",
+                                        }),
+                                        session_logs_dir,
+                                    );
+                                    denied_tool_calls.insert(tool.call_id.clone());
+                                    tool_responses.push(serde_json::json!({
+                                        "role": "tool",
+                                        "tool_call_id": tool.call_id,
+                                        "content": format!("❌ '{}' denied by tool policy", tool.invocation.tool)
+                                    }));
+                                    continue;
+                                }
+                                ToolPolicyAction::RequireLlmApproval => {
+                                    println!(
+                                        "🤖 Requesting approval from external LLM for '{}' tool...",
+                                        tool.invocation.tool
+                                    );
 
-                                        if approved {
-                                            println!(
-                                                "✅ Bugcrowd submission approved by external LLM: {}",
-                                                reasoning
-                                            );
-                                            // Let the tool call proceed normally
-                                        } else {
+                                    let tool_approval_prompt = inject_bugcrowd_approval_variables(
+                                        bugcrowd_approval_prompt_template,
+                                        &tool.invocation.tool,
+                                        &tool.invocation.arguments,
+                                    );
+
+                                    match resolve_llm_approval(
+                                        observability_hub,
+                                        &tool.call_id,
+                                        &tool_approval_prompt,
+                                        driver_model,
+                                        &session_logs_dir,
+                                        config_content,
+                                        "mcp_tool",
+                                        remote_approval_timeout,
+                                    )
+                                    .await
+                                    {
+                                        Ok((approved, reasoning, source)) => {
+                                            if approved {
+                                                println!(
+                                                    "✅ '{}' approved by {}: {}",
+                                                    tool.invocation.tool, source, reasoning
+                                                );
+                                                run_lifecycle_hooks(
+                                                    hook_table,
+                                                    "bugcrowd_submit_approved",
+                                                    &serde_json::json!({
+                                                        "tool": tool.invocation.tool,
+                                                        "reasoning": reasoning,
+                                                        "decided_by": source.to_string(),
+                                                    }),
+                                                    session_logs_dir,
+                                                );
+                                                // Let the tool call proceed normally
+                                            } else {
+                                                println!(
+                                                    "❌ '{}' denied by {}: {}",
+                                                    tool.invocation.tool, source, reasoning
+                                                );
+                                                run_lifecycle_hooks(
+                                                    hook_table,
+                                                    "tool_denied",
+                                                    &serde_json::json!({
+                                                        "tool": tool.invocation.tool,
+                                                        "reason": reasoning,
+                                                        "decided_by": source.to_string(),
+                                                    }),
+                                                    session_logs_dir,
+                                                );
+
+                                                // Track this call as denied so we ignore its McpToolCallEnd event
+                                                denied_tool_calls.insert(tool.call_id.clone());
+
+                                                // Create a fake tool response with the denial reasoning
+                                                // This prevents the actual MCP tool from being called
+                                                tool_responses.push(serde_json::json!({
+                                                    "role": "tool",
+                                                    "tool_call_id": tool.call_id,
+                                                    "decided_by": source.to_string(),
+                                                    "content": format!("❌ '{}' denied by security review: {}", tool.invocation.tool, reasoning)
+                                                }));
+
+                                                // Skip to next event - don't let this tool call proceed
+                                                continue;
+                                            }
+                                        }
+                                        Err(e) => {
                                             println!(
-                                                "❌ Bugcrowd submission denied by external LLM: {}",
-                                                reasoning
+                                                "❌ Error getting approval from external LLM: {}",
+                                                e
                                             );
 
-                                            // Track this call as denied so we ignore its McpToolCallEnd event
-                                            denied_tool_calls.insert(tool.call_id.clone());
-
-                                            // Create a fake tool response with the denial reasoning
-                                            // This prevents the actual MCP tool from being called
+                                            // Create a tool response with the error
                                             tool_responses.push(serde_json::json!({
                                                 "role": "tool",
                                                 "tool_call_id": tool.call_id,
-                                                "content": format!("❌ Bugcrowd submission denied by security review: {}", reasoning)
+                                                "content": format!("❌ '{}' call failed due to approval error: {}", tool.invocation.tool, e)
                                             }));
 
                                             // Skip to next event - don't let this tool call proceed
                                             continue;
                                         }
                                     }
-                                    Err(e) => {
-                                        println!(
-                                            "❌ Error getting approval from external LLM: {}",
-                                            e
-                                        );
-
-                                        // Create a tool response with the error
-                                        tool_responses.push(serde_json::json!({
-                                            "role": "tool",
-                                            "tool_call_id": tool.call_id,
-                                            "content": format!("❌ Bugcrowd submission failed due to approval error: {}", e)
-                                        }));
-
-                                        // Skip to next event - don't let this tool call proceed
-                                        continue;
-                                    }
                                 }
                             }
 
@@ -1180,52 +4043,98 @@ async fn collect_codex_response_with_tools(
                                 }
                             }));
 
-                            // Check if it's a bugcrowd_submit call - always require external LLM approval
-                            let is_bugcrowd_submit = approval.command.iter().any(|arg| {
-                                arg.contains("bugcrowd_submit") || arg.contains("bugcrowd-submit")
-                            });
-
-                            // Generate approval prompt with task context
-                            let approval_prompt = inject_approval_variables_with_context(
-                                approval_prompt_template,
+                            // The ordered approval_hooks pipeline gets first say; only
+                            // when no hook decides (or a matching hook explicitly says
+                            // `llm`) do we ask the external LLM, falling back to the
+                            // per-command tool policy table first if no hook fired at
+                            // all, so existing `tool_policies`-only configs are unaffected.
+                            let hook_decision = evaluate_exec_approval_hooks(
+                                approval_hooks,
                                 &approval.command,
                                 &approval.cwd,
-                                &approval.reason,
-                                &config_content,
-                            );
-
-                            let context_info = if is_bugcrowd_submit {
-                                " (BUGCROWD SUBMISSION - Requires careful review)"
-                            } else {
-                                ""
-                            };
-
-                            println!(
-                                "🤖 Requesting approval from external LLM{}...",
-                                context_info
-                            );
-
-                            let decision = match generate_user_prompt(
-                                &approval_prompt,
-                                driver_model,
-                                &session_logs_dir,
-                            )
-                            .await
-                            {
-                                Ok((response, _)) => {
-                                    println!("🤖 External LLM response: {}", response);
-                                    if response.to_lowercase().contains("approve") {
-                                        println!("✅ Approved by external LLM");
-                                        codex_core::protocol::ReviewDecision::Approved
-                                    } else {
-                                        println!("❌ Denied by external LLM");
-                                        codex_core::protocol::ReviewDecision::Denied
-                                    }
+                            )?;
+
+                            let (decision, decided_by) = match hook_decision {
+                                Some((hook_name, ApprovalHookOutcome::Allow)) => {
+                                    println!("✅ Approved by approval hook '{}'", hook_name);
+                                    (
+                                        codex_core::protocol::ReviewDecision::Approved,
+                                        format!("approval hook '{}'", hook_name),
+                                    )
                                 }
-                                Err(e) => {
-                                    println!("❌ Error getting approval from external LLM: {}", e);
-                                    codex_core::protocol::ReviewDecision::Denied
+                                Some((hook_name, ApprovalHookOutcome::Deny)) => {
+                                    println!("🚫 Denied by approval hook '{}'", hook_name);
+                                    (
+                                        codex_core::protocol::ReviewDecision::Denied,
+                                        format!("approval hook '{}'", hook_name),
+                                    )
+                                }
+                                Some((hook_name, ApprovalHookOutcome::Llm)) => {
+                                    let approval_prompt = inject_approval_variables_with_context(
+                                        approval_prompt_template,
+                                        &approval.command,
+                                        &approval.cwd,
+                                        &approval.reason,
+                                        &config_content,
+                                    );
+                                    println!(
+                                        "🤖 Approval hook '{}' escalated to external LLM...",
+                                        hook_name
+                                    );
+                                    let (decision, source) = resolve_review_llm_approval(
+                                        observability_hub,
+                                        &approval_id,
+                                        &approval_prompt,
+                                        driver_model,
+                                        &session_logs_dir,
+                                        config_content,
+                                        "exec",
+                                        remote_approval_timeout,
+                                    )
+                                    .await;
+                                    (decision, format!("approval hook '{}' ({})", hook_name, source))
+                                }
+                                Some((_, ApprovalHookOutcome::Defer)) => {
+                                    unreachable!("evaluate_exec_approval_hooks never returns Defer")
                                 }
+                                None => match resolve_command_tool_policy(tool_policy_table, &approval.command) {
+                                    ToolPolicyAction::AutoApprove => {
+                                        println!("✅ Approved per tool policy (no LLM consulted)");
+                                        (
+                                            codex_core::protocol::ReviewDecision::Approved,
+                                            "tool policy".to_string(),
+                                        )
+                                    }
+                                    ToolPolicyAction::Deny => {
+                                        println!("🚫 Denied per tool policy (no LLM consulted)");
+                                        (
+                                            codex_core::protocol::ReviewDecision::Denied,
+                                            "tool policy".to_string(),
+                                        )
+                                    }
+                                    ToolPolicyAction::RequireLlmApproval => {
+                                        let approval_prompt = inject_approval_variables_with_context(
+                                            approval_prompt_template,
+                                            &approval.command,
+                                            &approval.cwd,
+                                            &approval.reason,
+                                            &config_content,
+                                        );
+                                        println!("🤖 Requesting approval from external LLM...");
+                                        let (decision, source) = resolve_review_llm_approval(
+                                            observability_hub,
+                                            &approval_id,
+                                            &approval_prompt,
+                                            driver_model,
+                                            &session_logs_dir,
+                                            config_content,
+                                            "exec",
+                                            remote_approval_timeout,
+                                        )
+                                        .await;
+                                        (decision, source)
+                                    }
+                                },
                             };
 
                             // Add approval decision as a tool response
@@ -1234,10 +4143,11 @@ async fn collect_codex_response_with_tools(
                                 "tool_call_id": approval_id,
                                 "content": serde_json::to_string(&serde_json::json!({
                                     "decision": decision,
+                                    "decided_by": decided_by,
                                     "llm_response": match &decision {
-                                        codex_core::protocol::ReviewDecision::Approved => "✅ Approved by external LLM",
-                                        codex_core::protocol::ReviewDecision::Denied => "❌ Denied by external LLM",
-                                        _ => "❓ Unknown decision"
+                                        codex_core::protocol::ReviewDecision::Approved => format!("✅ Approved by {}", decided_by),
+                                        codex_core::protocol::ReviewDecision::Denied => format!("❌ Denied by {}", decided_by),
+                                        _ => "❓ Unknown decision".to_string()
                                     }
                                 })).unwrap_or_default()
                             }));
@@ -1287,31 +4197,63 @@ async fn collect_codex_response_with_tools(
                                     &config_content,
                                 );
 
-                            println!("🤖 Requesting patch approval from external LLM...");
-
-                            let decision = match generate_user_prompt(
-                                &patch_approval_prompt,
-                                driver_model,
-                                &session_logs_dir,
-                            )
-                            .await
-                            {
-                                Ok((response, _)) => {
-                                    println!("🤖 External LLM response: {}", response);
-                                    if response.to_lowercase().contains("approve") {
-                                        println!("✅ Patch approved by external LLM");
-                                        codex_core::protocol::ReviewDecision::Approved
-                                    } else {
-                                        println!("❌ Patch denied by external LLM");
-                                        codex_core::protocol::ReviewDecision::Denied
-                                    }
+                            // Same ordered approval_hooks pipeline as ExecApprovalRequest,
+                            // matched against the patch's changed files instead of a
+                            // command; falls back to the external LLM exactly as before
+                            // when no hook decides.
+                            let hook_decision =
+                                evaluate_patch_approval_hooks(approval_hooks, &patch_approval.changes)?;
+
+                            let (decision, decided_by) = match &hook_decision {
+                                Some((hook_name, ApprovalHookOutcome::Allow)) => {
+                                    println!("✅ Patch approved by approval hook '{}'", hook_name);
+                                    (
+                                        codex_core::protocol::ReviewDecision::Approved,
+                                        format!("approval hook '{}'", hook_name),
+                                    )
                                 }
-                                Err(e) => {
+                                Some((hook_name, ApprovalHookOutcome::Deny)) => {
+                                    println!("🚫 Patch denied by approval hook '{}'", hook_name);
+                                    (
+                                        codex_core::protocol::ReviewDecision::Denied,
+                                        format!("approval hook '{}'", hook_name),
+                                    )
+                                }
+                                Some((_, ApprovalHookOutcome::Defer)) => {
+                                    unreachable!("evaluate_patch_approval_hooks never returns Defer")
+                                }
+                                Some((hook_name, ApprovalHookOutcome::Llm)) => {
                                     println!(
-                                        "❌ Error getting patch approval from external LLM: {}",
-                                        e
+                                        "🤖 Approval hook '{}' escalated to external LLM...",
+                                        hook_name
                                     );
-                                    codex_core::protocol::ReviewDecision::Denied
+                                    let (decision, source) = resolve_review_llm_approval(
+                                        observability_hub,
+                                        &approval_id,
+                                        &patch_approval_prompt,
+                                        driver_model,
+                                        &session_logs_dir,
+                                        config_content,
+                                        "patch",
+                                        remote_approval_timeout,
+                                    )
+                                    .await;
+                                    (decision, format!("approval hook '{}' ({})", hook_name, source))
+                                }
+                                None => {
+                                    println!("🤖 Requesting patch approval from external LLM...");
+                                    let (decision, source) = resolve_review_llm_approval(
+                                        observability_hub,
+                                        &approval_id,
+                                        &patch_approval_prompt,
+                                        driver_model,
+                                        &session_logs_dir,
+                                        config_content,
+                                        "patch",
+                                        remote_approval_timeout,
+                                    )
+                                    .await;
+                                    (decision, source)
                                 }
                             };
 
@@ -1321,10 +4263,11 @@ async fn collect_codex_response_with_tools(
                                 "tool_call_id": approval_id,
                                 "content": serde_json::to_string(&serde_json::json!({
                                     "decision": decision,
+                                    "decided_by": decided_by,
                                     "llm_response": match &decision {
-                                        codex_core::protocol::ReviewDecision::Approved => "✅ Patch approved by external LLM",
-                                        codex_core::protocol::ReviewDecision::Denied => "❌ Patch denied by external LLM",
-                                        _ => "❓ Unknown decision"
+                                        codex_core::protocol::ReviewDecision::Approved => format!("✅ Patch approved by {}", decided_by),
+                                        codex_core::protocol::ReviewDecision::Denied => format!("❌ Patch denied by {}", decided_by),
+                                        _ => "❓ Unknown decision".to_string()
                                     }
                                 })).unwrap_or_default()
                             }));
@@ -1417,7 +4360,64 @@ async fn collect_codex_response_with_tools(
                 }
             }
             Err(e) => {
-                return Err(anyhow::anyhow!("Error receiving event: {}", e));
+                let message = e.to_string();
+                // Stream idle timeouts and disconnects are expected to clear
+                // up on their own; anything else (e.g. a malformed request)
+                // won't be fixed by retrying, so treat it as fatal right away.
+                let is_transient = message.to_lowercase().contains("idle")
+                    || message.to_lowercase().contains("timeout")
+                    || message.to_lowercase().contains("disconnect")
+                    || message.to_lowercase().contains("connection")
+                    || message.to_lowercase().contains("closed");
+                let max_retries = model_provider.stream_max_retries.unwrap_or(5);
+
+                if is_transient && stream_retry_count < max_retries {
+                    stream_retry_count += 1;
+                    let base_delay_ms = model_provider.stream_idle_timeout_ms.unwrap_or(30_000);
+                    let backoff_ms =
+                        base_delay_ms.saturating_mul(1 << (stream_retry_count - 1).min(5));
+                    println!(
+                        "⚠️  Transient stream error ({}/{}), retrying in {}ms: {}",
+                        stream_retry_count, max_retries, backoff_ms, message
+                    );
+                    tool_calls.push(serde_json::json!({
+                        "id": format!("event_streamretry_{}", std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis()),
+                        "type": "system",
+                        "function": {
+                            "name": "background_event",
+                            "arguments": serde_json::to_string(&serde_json::json!({
+                                "message": format!(
+                                    "stream error, retrying ({}/{}) in {}ms: {}",
+                                    stream_retry_count, max_retries, backoff_ms, message
+                                )
+                            })).unwrap_or_default()
+                        }
+                    }));
+                    tokio::time::sleep(std::time::Duration::from_millis(backoff_ms)).await;
+                    continue;
+                }
+
+                println!(
+                    "❌ Giving up on stream after {} retries: {}",
+                    stream_retry_count, message
+                );
+                tool_calls.push(serde_json::json!({
+                    "id": format!("event_streamfatal_{}", std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis()),
+                    "type": "system",
+                    "function": {
+                        "name": "background_event",
+                        "arguments": serde_json::to_string(&serde_json::json!({
+                            "message": format!(
+                                "giving up receiving events after {} retries: {}",
+                                stream_retry_count, message
+                            )
+                        })).unwrap_or_default()
+                    }
+                }));
+                // Stop the loop rather than propagating an error so the
+                // partial transcript collected so far is still returned to
+                // the caller instead of discarding the whole iteration.
+                task_complete = true;
             }
         }
     }
@@ -1553,52 +4553,376 @@ fn parse_approval_response(response: &str) -> (bool, String) {
     }
 }
 
+/// Where a resolved approve/deny decision actually came from, so callers can
+/// record it in `tool_responses`/log messages instead of always attributing
+/// it to the external LLM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ApprovalSource {
+    Human,
+    Llm,
+}
+
+impl std::fmt::Display for ApprovalSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApprovalSource::Human => write!(f, "human operator (remote approval channel)"),
+            ApprovalSource::Llm => write!(f, "external LLM"),
+        }
+    }
+}
+
+/// Resolve a pending `RequireLlmApproval` decision for `approval_id`. If an
+/// operator already submitted one over the `--serve` control channel, use it
+/// directly and skip the network call entirely. Otherwise, when a hub is
+/// configured, publish the request over the channel (`kind` is `"exec"`,
+/// `"patch"`, `"mcp_tool"`, or `"plugin_tool"`) and hold it open for `remote_approval_timeout`
+/// in case a human responds in that window. Only once that window lapses
+/// (or there's no hub at all) does this fall back to asking the external LLM
+/// via `generate_user_prompt` and parsing its response the usual way.
+async fn resolve_llm_approval(
+    observability_hub: Option<&ObservabilityHub>,
+    approval_id: &str,
+    prompt: &str,
+    driver_model: &str,
+    session_logs_dir: &std::path::Path,
+    config_content: &str,
+    kind: &str,
+    remote_approval_timeout: std::time::Duration,
+) -> anyhow::Result<(bool, String, ApprovalSource)> {
+    if let Some(decision) = observability_hub.and_then(|hub| hub.take_remote_decision(approval_id))
+    {
+        println!(
+            "🛰️  Using operator-submitted decision for '{}' from the control channel",
+            approval_id
+        );
+        return Ok((
+            decision,
+            "decided by operator via observability control channel".to_string(),
+            ApprovalSource::Human,
+        ));
+    }
+
+    if let Some(hub) = observability_hub {
+        hub.publish_approval_request(kind, approval_id, prompt);
+        println!(
+            "🛰️  Published '{}' for remote approval, waiting up to {}s for an operator...",
+            approval_id,
+            remote_approval_timeout.as_secs()
+        );
+        if let Some(decision) = hub.await_remote_decision(approval_id, remote_approval_timeout).await {
+            println!(
+                "🛰️  Operator responded to '{}' within the wait window",
+                approval_id
+            );
+            return Ok((
+                decision,
+                "decided by operator via observability control channel".to_string(),
+                ApprovalSource::Human,
+            ));
+        }
+        println!(
+            "⏱️  No operator response for '{}' within {}s, falling back to the external LLM",
+            approval_id,
+            remote_approval_timeout.as_secs()
+        );
+    }
+
+    let (response, _) =
+        generate_user_prompt(prompt, driver_model, session_logs_dir, &[], config_content).await?;
+    println!("🤖 External LLM response: {}", response);
+    let (approved, reasoning) = parse_approval_response(&response);
+    Ok((approved, reasoning, ApprovalSource::Llm))
+}
+
+/// [`resolve_llm_approval`], mapped to a `ReviewDecision` (with errors folded
+/// into deny-for-safety instead of propagated) plus a `decided_by` label
+/// naming whichever of the human or the LLM actually made the call, for the
+/// `ExecApprovalRequest`/`ApplyPatchApprovalRequest` call sites (tool-policy
+/// fallback and approval-hook `llm` escalation) that all need exactly this.
+async fn resolve_review_llm_approval(
+    observability_hub: Option<&ObservabilityHub>,
+    approval_id: &str,
+    prompt: &str,
+    driver_model: &str,
+    session_logs_dir: &std::path::Path,
+    config_content: &str,
+    kind: &str,
+    remote_approval_timeout: std::time::Duration,
+) -> (codex_core::protocol::ReviewDecision, String) {
+    match resolve_llm_approval(
+        observability_hub,
+        approval_id,
+        prompt,
+        driver_model,
+        session_logs_dir,
+        config_content,
+        kind,
+        remote_approval_timeout,
+    )
+    .await
+    {
+        Ok((true, _, source)) => {
+            println!("✅ Approved by {}", source);
+            (codex_core::protocol::ReviewDecision::Approved, source.to_string())
+        }
+        Ok((false, _, source)) => {
+            println!("❌ Denied by {}", source);
+            (codex_core::protocol::ReviewDecision::Denied, source.to_string())
+        }
+        Err(e) => {
+            println!("❌ Error getting approval from external LLM: {}", e);
+            (
+                codex_core::protocol::ReviewDecision::Denied,
+                "external LLM".to_string(),
+            )
+        }
+    }
+}
+
 fn count_tokens(text: &str) -> anyhow::Result<usize> {
     let bpe = o200k_base().context("Failed to load o200k_base encoding")?;
     let tokens = bpe.encode_with_special_tokens(text);
     Ok(tokens.len())
 }
 
-async fn summarize_context(
-    context: &str,
-    model: &str,
+/// Render the conversation log back into the flat `ROLE: content` string the
+/// driver prompt templates expect, mirroring the structure used when events
+/// are first appended to `conversation_log`.
+fn build_readable_context(conversation_log: &[serde_json::Value]) -> String {
+    let mut readable_context = String::new();
+    for msg in conversation_log {
+        match msg.get("role").and_then(|r| r.as_str()) {
+            Some("system") => {
+                readable_context.push_str(&format!(
+                    "SYSTEM: {}\n\n",
+                    msg.get("content").and_then(|c| c.as_str()).unwrap_or("")
+                ));
+            }
+            Some("user") => {
+                readable_context.push_str(&format!(
+                    "USER: {}\n\n",
+                    msg.get("content").and_then(|c| c.as_str()).unwrap_or("")
+                ));
+            }
+            Some("assistant") => {
+                if let Some(reasoning) = msg.get("reasoning") {
+                    readable_context.push_str(&format!(
+                        "ASSISTANT_REASONING: {}\n\n",
+                        reasoning.as_str().unwrap_or("")
+                    ));
+                } else if let Some(tool_calls) = msg.get("tool_calls") {
+                    // Filter out system tool calls
+                    let empty_vec = vec![];
+                    let tool_calls_array = tool_calls.as_array().unwrap_or(&empty_vec);
+                    let filtered_tool_calls: Vec<_> = tool_calls_array
+                        .iter()
+                        .filter(|tool_call| {
+                            tool_call.get("type").and_then(|t| t.as_str()) != Some("system")
+                        })
+                        .collect();
+
+                    if !filtered_tool_calls.is_empty() {
+                        readable_context.push_str(&format!(
+                            "ASSISTANT_TOOL_CALLS: {}\n\n",
+                            serde_json::to_string_pretty(&filtered_tool_calls).unwrap_or_default()
+                        ));
+                    }
+                } else {
+                    readable_context.push_str(&format!(
+                        "ASSISTANT: {}\n\n",
+                        msg.get("content").and_then(|c| c.as_str()).unwrap_or("")
+                    ));
+                }
+            }
+            Some("tool") => {
+                readable_context.push_str(&format!(
+                    "TOOL_RESPONSE: {}\n\n",
+                    msg.get("content").and_then(|c| c.as_str()).unwrap_or("")
+                ));
+            }
+            _ => {
+                // Skip unknown roles
+            }
+        }
+    }
+    readable_context
+}
+
+/// The deterministic, network-free half of compaction: whether the log's
+/// encoded token count exceeds budget and, if so, exactly which contiguous
+/// prefix (after any system message) would be summarized away. Shared by
+/// [`compact_conversation_log_if_needed`] and the `replay` subcommand so the
+/// two can never disagree about whether a recorded session's token
+/// thresholds would still trigger compaction today.
+struct CompactionPlan {
+    total_tokens: usize,
+    system_end: usize,
+    end: usize,
+}
+
+/// The end of the "turn" starting at `conversation_log[index]`: if that
+/// entry carries `tool_calls`, every immediately-following `"tool"`-role
+/// entry is one of that turn's responses, so the turn doesn't end until
+/// they do. Used to walk the compaction boundary in whole-turn steps
+/// instead of per-message, so it can never land between a `tool_calls`
+/// message and (one of) its responses.
+fn turn_end_at(conversation_log: &[serde_json::Value], index: usize) -> usize {
+    let mut turn_end = index + 1;
+    let has_tool_calls = conversation_log[index]
+        .get("tool_calls")
+        .and_then(|v| v.as_array())
+        .map(|arr| !arr.is_empty())
+        .unwrap_or(false);
+    if has_tool_calls {
+        while turn_end < conversation_log.len()
+            && conversation_log[turn_end].get("role").and_then(|r| r.as_str()) == Some("tool")
+        {
+            turn_end += 1;
+        }
+    }
+    turn_end
+}
+
+fn compute_compaction_plan(
+    conversation_log: &[serde_json::Value],
+    max_context_tokens: usize,
+) -> anyhow::Result<Option<CompactionPlan>> {
+    let budget = max_context_tokens.saturating_sub(TOKEN_BUFFER);
+    let low_water_mark = (max_context_tokens * 6) / 10;
+
+    let serialized = serde_json::to_string(conversation_log).unwrap_or_default();
+    let total_tokens = count_tokens(&serialized)?;
+    if total_tokens <= budget {
+        return Ok(None);
+    }
+
+    let system_end = if conversation_log
+        .first()
+        .and_then(|m| m.get("role"))
+        .and_then(|r| r.as_str())
+        == Some("system")
+    {
+        1
+    } else {
+        0
+    };
+    let keep_from = conversation_log
+        .len()
+        .saturating_sub(KEEP_LAST_MESSAGES)
+        .max(system_end);
+
+    let mut end = system_end;
+    while end < keep_from {
+        let next = turn_end_at(conversation_log, end);
+        if next > keep_from {
+            // This turn's tool responses reach into the most recent
+            // `KEEP_LAST_MESSAGES` messages; stop before it.
+            break;
+        }
+        end = next;
+        let remaining = serde_json::to_string(&conversation_log[end..]).unwrap_or_default();
+        if count_tokens(&remaining)? <= low_water_mark {
+            break;
+        }
+    }
+
+    if end <= system_end {
+        // Nothing can be summarized without touching the system prompt or
+        // the most recent messages; leave the log as-is.
+        return Ok(None);
+    }
+
+    Ok(Some(CompactionPlan {
+        total_tokens,
+        system_end,
+        end,
+    }))
+}
+
+/// Compact `conversation_log` in place if its encoded token count exceeds
+/// `max_context_tokens` minus `TOKEN_BUFFER`: the oldest non-system messages
+/// (a contiguous prefix after the system message) are summarized by the
+/// driver model and spliced back in as a single synthetic system message,
+/// stopping once the remaining log is back under the low-water mark (60% of
+/// `max_context_tokens`) or only the last `KEEP_LAST_MESSAGES` messages remain. The
+/// original system prompt and those most-recent messages are always kept
+/// verbatim so tool-call/tool-result pairs are never split. Returns a
+/// checkpoint-able event describing the compaction, or `None` if nothing
+/// needed to be done.
+async fn compact_conversation_log_if_needed(
+    conversation_log: &mut Vec<serde_json::Value>,
+    max_context_tokens: usize,
+    driver_model: &str,
     summarization_prompt_template: &str,
-) -> anyhow::Result<String> {
-    let summarization_prompt = summarization_prompt_template.replace("{context}", context);
+    config_content: &str,
+) -> anyhow::Result<Option<serde_json::Value>> {
+    let Some(plan) = compute_compaction_plan(conversation_log, max_context_tokens)? else {
+        return Ok(None);
+    };
 
     println!(
-        "🔄 Context too long ({} tokens), summarizing...",
-        count_tokens(context).unwrap_or(0)
+        "⚠️  Context approaching token limit: {} tokens (max: {}), compacting oldest {} messages",
+        plan.total_tokens,
+        max_context_tokens,
+        plan.end - plan.system_end
     );
+
+    let to_summarize =
+        serde_json::to_string_pretty(&conversation_log[plan.system_end..plan.end])
+            .unwrap_or_default();
+    let summarization_prompt = summarization_prompt_template.replace("{context}", &to_summarize);
     let (summary, _) = generate_user_prompt(
         &summarization_prompt,
-        model,
-        &std::path::Path::new("./logs"),
+        driver_model,
+        std::path::Path::new("./logs"),
+        &[],
+        config_content,
     )
     .await?;
-    println!(
-        "✅ Context summarized from {} to {} tokens",
-        count_tokens(context).unwrap_or(0),
-        count_tokens(&summary).unwrap_or(0)
+
+    let messages_removed = plan.end - plan.system_end;
+    conversation_log.splice(
+        plan.system_end..plan.end,
+        std::iter::once(serde_json::json!({
+            "role": "system",
+            "content": summary,
+            "compacted": true,
+        })),
     );
 
-    Ok(summary)
+    Ok(Some(serde_json::json!({
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "messages_removed": messages_removed,
+        "tokens_before": plan.total_tokens,
+    })))
 }
 
-async fn generate_user_prompt(
-    driver_prompt: &str,
+/// Call `model`'s Responses API with `input` as the full conversation so far
+/// (resent in its entirety on every call, tools included, so earlier tool
+/// results stay visible to the model) and return any plain text produced
+/// plus the raw `FunctionCall` items the model asked for, both as
+/// `ResponseItem`s (for callers threading them back into their own running
+/// conversation) and as the OpenAI-style JSON the rest of this file already
+/// works with. Shared by [`generate_user_prompt`] (a one-off single-message
+/// call, used for compaction summaries and external-LLM approvals) and [`run_driver_tool_loop`] (which
+/// owns the growing multi-step conversation).
+async fn generate_driver_response(
+    input: Vec<codex_protocol::models::ResponseItem>,
     model: &str,
-    session_logs_dir: &std::path::Path,
-) -> anyhow::Result<(String, Vec<serde_json::Value>)> {
+    plugin_tools: &[(String, PluginToolSignature)],
+    config_content: &str,
+) -> anyhow::Result<(
+    String,
+    Vec<codex_protocol::models::ResponseItem>,
+    Vec<serde_json::Value>,
+)> {
     use codex_core::client::ModelClient;
     use codex_core::client_common::Prompt;
     use codex_core::config::Config;
     use codex_core::config::ConfigOverrides;
-    use codex_core::model_provider_info::ModelProviderInfo;
-    use codex_core::model_provider_info::WireApi;
+    use codex_core::openai_tools::mcp_tool_to_openai_tool;
     use codex_protocol::config_types::{ReasoningEffort, ReasoningSummary};
     use codex_protocol::models::ContentItem;
-    use codex_protocol::models::FunctionCallOutputPayload;
     use codex_protocol::models::ResponseItem;
     use futures::StreamExt;
     use std::sync::Arc;
@@ -1606,21 +4930,12 @@ async fn generate_user_prompt(
 
     println!("🔄 Calling {} with driver prompt...", model);
 
-    // Create model provider info - use OpenRouter for consistency
-    let provider = ModelProviderInfo {
-        name: "OpenRouter".to_string(),
-        base_url: Some("https://openrouter.ai/api/v1".to_string()),
-        env_key: Some("OPENROUTER_API_KEY".to_string()),
-        env_key_instructions: None,
-        wire_api: WireApi::Chat,
-        query_params: None,
-        env_http_headers: None,
-        http_headers: None,
-        request_max_retries: Some(3),
-        stream_max_retries: Some(5),
-        stream_idle_timeout_ms: Some(30000),
-        requires_openai_auth: false,
-    };
+    // Pick the backend for this model from the config file's `providers`
+    // section (falling back to the built-in OpenRouter provider if it has
+    // none, or none of its entries match).
+    let provider_registry = load_provider_registry(config_content)?;
+    let (provider, model) = resolve_provider_for_model(&provider_registry, model)?;
+    let model = model.as_str();
 
     // Create minimal config for the driver model client
     let driver_config = Arc::new(Config::load_with_cli_overrides(
@@ -1642,15 +4957,6 @@ async fn generate_user_prompt(
         Uuid::new_v4(), // Generate session ID
     );
 
-    // Create prompt with driver prompt as user message
-    let user_message = ResponseItem::Message {
-        id: None,
-        role: "user".to_string(),
-        content: vec![ContentItem::InputText {
-            text: driver_prompt.to_string(),
-        }],
-    };
-
     // Create note-taking tools
     let mut extra_tools = std::collections::HashMap::new();
 
@@ -1753,10 +5059,45 @@ async fn generate_user_prompt(
         },
     );
 
+    // Plugin-provided tools, namespaced as "plugin:<plugin_name>:<tool_name>"
+    for (namespaced_name, signature) in plugin_tools {
+        extra_tools.insert(
+            namespaced_name.clone(),
+            mcp_types::Tool {
+                name: namespaced_name.clone(),
+                description: Some(signature.description.clone()),
+                title: None,
+                annotations: None,
+                input_schema: mcp_types::ToolInputSchema {
+                    r#type: "object".to_string(),
+                    properties: signature.arguments_schema.get("properties").cloned(),
+                    required: signature
+                        .arguments_schema
+                        .get("required")
+                        .and_then(|v| v.as_array())
+                        .map(|arr| {
+                            arr.iter()
+                                .filter_map(|item| item.as_str().map(|s| s.to_string()))
+                                .collect()
+                        }),
+                },
+                output_schema: None,
+            },
+        );
+    }
+
+    // Every `extra_tools` entry (note-taking, the Slack/finish tools, and any
+    // plugin tools) is sent on every call, not just the first, so the model
+    // can keep calling them across the whole multi-step loop.
+    let tools = extra_tools
+        .into_values()
+        .map(mcp_tool_to_openai_tool)
+        .collect();
+
     let prompt = Prompt {
-        input: vec![user_message.clone()],
+        input,
         store: false,
-        tools: vec![], // Will be populated by OpenAI tools conversion
+        tools,
         base_instructions_override: None,
     };
 
@@ -1767,38 +5108,27 @@ async fn generate_user_prompt(
         .with_context(|| "Failed to create response stream")?;
 
     let mut response_text = String::new();
-    let mut tool_calls = Vec::new();
+    let mut function_call_items = Vec::new();
 
     // Collect the response
     while let Some(event) = response_stream.next().await {
         match event {
             Ok(response_event) => {
                 match response_event {
-                    codex_core::client_common::ResponseEvent::OutputItemDone(item) => match item {
+                    codex_core::client_common::ResponseEvent::OutputItemDone(item) => match &item
+                    {
                         ResponseItem::Message { content, .. } => {
                             for content_item in content {
                                 match content_item {
                                     ContentItem::OutputText { text } => {
-                                        response_text.push_str(&text);
+                                        response_text.push_str(text);
                                     }
                                     _ => {}
                                 }
                             }
                         }
-                        ResponseItem::FunctionCall {
-                            id: _,
-                            name,
-                            arguments,
-                            call_id,
-                        } => {
-                            tool_calls.push(serde_json::json!({
-                                "id": call_id,
-                                "type": "function",
-                                "function": {
-                                    "name": name,
-                                    "arguments": arguments
-                                }
-                            }));
+                        ResponseItem::FunctionCall { .. } => {
+                            function_call_items.push(item);
                         }
                         _ => {}
                     },
@@ -1816,120 +5146,808 @@ async fn generate_user_prompt(
         }
     }
 
-    // Handle tool calls
-    if !tool_calls.is_empty() {
-        let (tool_results, _finished) =
-            handle_supervisor_tool_calls(&tool_calls, session_logs_dir).await?;
-
-        // Add tool calls and results to conversation and get new instruction
-        let mut conversation = vec![user_message];
-
-        // Add the assistant's response with tool calls
-        conversation.push(ResponseItem::Message {
-            id: None,
-            role: "assistant".to_string(),
-            content: if response_text.trim().is_empty() {
-                vec![]
-            } else {
-                vec![ContentItem::OutputText {
-                    text: response_text.trim().to_string(),
-                }]
-            },
-        });
+    let tool_calls: Vec<serde_json::Value> = function_call_items
+        .iter()
+        .map(|item| match item {
+            ResponseItem::FunctionCall {
+                name,
+                arguments,
+                call_id,
+                ..
+            } => serde_json::json!({
+                "id": call_id,
+                "type": "function",
+                "function": {
+                    "name": name,
+                    "arguments": arguments
+                }
+            }),
+            _ => unreachable!("function_call_items only ever holds FunctionCall items"),
+        })
+        .collect();
+
+    // Return the raw tool calls the driver model requested, if any, without
+    // executing them: `run_driver_tool_loop` owns execution (with caching)
+    // and decides whether to loop back for another round.
+    if !tool_calls.is_empty() {
+        if !driver_model_supports_tools(model) {
+            return Err(anyhow::anyhow!(
+                "Driver model '{}' returned tool calls but is not a known function-calling model",
+                model
+            ));
+        }
+        return Ok((response_text.trim().to_string(), function_call_items, tool_calls));
+    }
+
+    if response_text.is_empty() {
+        return Err(anyhow::anyhow!("No response received from external LLM"));
+    }
+
+    Ok((response_text.trim().to_string(), Vec::new(), Vec::new()))
+}
+
+/// Single-message wrapper around [`generate_driver_response`] for one-off
+/// calls that don't need the multi-step conversation threaded through (the
+/// compaction summarizer and [`resolve_llm_approval`]).
+async fn generate_user_prompt(
+    driver_prompt: &str,
+    model: &str,
+    _session_logs_dir: &std::path::Path,
+    plugin_tools: &[(String, PluginToolSignature)],
+    config_content: &str,
+) -> anyhow::Result<(String, Vec<serde_json::Value>)> {
+    use codex_protocol::models::ContentItem;
+    use codex_protocol::models::ResponseItem;
+
+    let user_message = ResponseItem::Message {
+        id: None,
+        role: "user".to_string(),
+        content: vec![ContentItem::InputText {
+            text: driver_prompt.to_string(),
+        }],
+    };
+
+    let (response_text, _function_call_items, tool_calls) =
+        generate_driver_response(vec![user_message], model, plugin_tools, config_content).await?;
+    Ok((response_text, tool_calls))
+}
+
+/// Driver models known to support OpenAI-style function calling. Used to
+/// fail fast with a clear error rather than silently dropping tool calls a
+/// non-tool-capable model has no business emitting.
+const FUNCTION_CALLING_DRIVER_MODELS: &[&str] = &[
+    "o3",
+    "o3-mini",
+    "o4-mini",
+    "gpt-4o",
+    "gpt-4.1",
+    "gpt-4-turbo",
+    "claude-3-5-sonnet",
+    "claude-3-7-sonnet",
+];
+
+fn driver_model_supports_tools(model: &str) -> bool {
+    FUNCTION_CALLING_DRIVER_MODELS
+        .iter()
+        .any(|known| model.starts_with(known))
+}
+
+/// Maximum number of driver-model/tool-execution round trips to allow
+/// within a single `run_driver_tool_loop` call before giving up. Bounds a
+/// driver model that keeps calling tools instead of ever returning a plain
+/// instruction.
+const MAX_TOOL_STEPS: u32 = 8;
+
+/// Drive the supervisor (driver) model through as many tool-call/tool-result
+/// round trips as it asks for, appending each step to `conversation_log`,
+/// and return the final plain-text instruction to submit to codex plus
+/// whether the `finished` tool was called (in which case the caller should
+/// end the whole autonomous session, not just this iteration).
+///
+/// Unlike a single-shot call, every step resends the entire accumulated
+/// conversation (the initial prompt, each `FunctionCall` the model made, and
+/// each `FunctionCallOutput` it got back) to `generate_driver_response` with
+/// tools still enabled, exactly as aichat's multi-step function calling
+/// does, so earlier note/tool results stay visible to the model instead of
+/// being collapsed into a re-templated text blob. The loop stops when a step
+/// returns no tool calls, the `finished` tool sets `session_finished`, or
+/// `MAX_TOOL_STEPS` is hit (an error, not a silent truncation).
+async fn run_driver_tool_loop(
+    initial_driver_prompt: &str,
+    driver_model: &str,
+    session_logs_dir: &std::path::Path,
+    config_content: &str,
+    conversation_log: &mut Vec<serde_json::Value>,
+    tool_cache: &mut std::collections::HashMap<String, serde_json::Value>,
+    plugin_registry: &mut PluginRegistry,
+    tool_policy_table: &std::collections::HashMap<String, ToolPolicyAction>,
+    bugcrowd_approval_prompt_template: &str,
+    hook_table: &std::collections::HashMap<String, Vec<HookConfig>>,
+    observability_hub: Option<&ObservabilityHub>,
+    remote_approval_timeout: std::time::Duration,
+    tool_confirmation_mode: ToolConfirmationMode,
+) -> anyhow::Result<(String, bool)> {
+    use codex_protocol::models::ContentItem;
+    use codex_protocol::models::ResponseItem;
+
+    let plugin_tools = plugin_registry.namespaced_tools();
+
+    // Argument schemas used to validate each tool call before it runs:
+    // built-ins plus every plugin tool's own declared schema.
+    let mut tool_schema_table: std::collections::HashMap<String, ToolArgumentSchema> =
+        builtin_tool_argument_schemas()
+            .into_iter()
+            .map(|(name, schema)| (name.to_string(), schema))
+            .collect();
+    for (namespaced_name, signature) in &plugin_tools {
+        let required = signature
+            .arguments_schema
+            .get("required")
+            .and_then(|v| v.as_array())
+            .map(|arr| {
+                arr.iter()
+                    .filter_map(|item| item.as_str().map(String::from))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let properties = signature
+            .arguments_schema
+            .get("properties")
+            .cloned()
+            .unwrap_or_else(|| serde_json::json!({}));
+        tool_schema_table.insert(namespaced_name.clone(), ToolArgumentSchema { required, properties });
+    }
+
+    let mut conversation: Vec<ResponseItem> = vec![ResponseItem::Message {
+        id: None,
+        role: "user".to_string(),
+        content: vec![ContentItem::InputText {
+            text: initial_driver_prompt.to_string(),
+        }],
+    }];
+
+    for step in 0..MAX_TOOL_STEPS {
+        let (supervisor_message, function_call_items, tool_calls) = generate_driver_response(
+            conversation.clone(),
+            driver_model,
+            &plugin_tools,
+            config_content,
+        )
+        .await?;
+
+        println!("💭 Generated user prompt: {}", supervisor_message);
+
+        // A model occasionally echoes the same tool-call id twice in one
+        // response; keep only the first occurrence of each id so it's
+        // never executed (or counted) twice within this step.
+        let mut seen_call_ids = std::collections::HashSet::new();
+        let (function_call_items, tool_calls): (Vec<ResponseItem>, Vec<serde_json::Value>) =
+            function_call_items
+                .into_iter()
+                .zip(tool_calls)
+                .filter(|(_, call)| {
+                    seen_call_ids.insert(call["id"].as_str().unwrap_or("unknown").to_string())
+                })
+                .unzip();
+
+        if tool_calls.is_empty() {
+            conversation_log.push(serde_json::json!({
+                "role": "user",
+                "content": supervisor_message
+            }));
+            return Ok((supervisor_message, false));
+        }
+
+        println!(
+            "🔁 Driver tool step {}/{}: {} tool call(s)",
+            step + 1,
+            MAX_TOOL_STEPS,
+            tool_calls.len()
+        );
+
+        // Add the assistant message with tool calls to the conversation log
+        // and thread the raw `FunctionCall` items into the next request.
+        conversation_log.push(serde_json::json!({
+            "role": "user",
+            "content": supervisor_message,
+            "tool_calls": tool_calls
+        }));
+        conversation.extend(function_call_items);
+
+        // Execute each tool call, reusing a cached result for any call whose
+        // tool name + serialized arguments we've already seen this session.
+        // `call_cache_keys` remembers which cache key each still-to-execute
+        // call's id maps to, so results can be written back into the cache
+        // once `handle_supervisor_tool_calls` returns them.
+        let mut tool_results = Vec::new();
+        let mut calls_to_execute = Vec::new();
+        let mut call_cache_keys = std::collections::HashMap::new();
+        for tool_call in &tool_calls {
+            let tool_id = tool_call["id"].as_str().unwrap_or("unknown").to_string();
+            let cache_key = format!(
+                "{}:{}",
+                tool_call["function"]["name"].as_str().unwrap_or("unknown"),
+                tool_call["function"]["arguments"]
+            );
+            if let Some(cached) = tool_cache.get(&cache_key) {
+                tool_results.push(serde_json::json!({
+                    "tool_call_id": tool_id,
+                    "tool_name": tool_call["function"]["name"],
+                    "content": cached["content"]
+                }));
+            } else {
+                call_cache_keys.insert(tool_id, cache_key);
+                calls_to_execute.push(tool_call.clone());
+            }
+        }
+
+        let (fresh_results, finished) = handle_supervisor_tool_calls(
+            &calls_to_execute,
+            session_logs_dir,
+            plugin_registry,
+            tool_policy_table,
+            &tool_schema_table,
+            driver_model,
+            bugcrowd_approval_prompt_template,
+            hook_table,
+            observability_hub,
+            config_content,
+            remote_approval_timeout,
+            tool_confirmation_mode,
+        )
+        .await?;
+        for fresh_result in &fresh_results {
+            let tool_id = fresh_result["tool_call_id"].as_str().unwrap_or("unknown");
+            if let Some(cache_key) = call_cache_keys.get(tool_id) {
+                tool_cache.insert(cache_key.clone(), fresh_result.clone());
+            }
+        }
+        tool_results.extend(fresh_results);
+
+        // Add tool results to the conversation log and feed each one back as
+        // a `FunctionCallOutput` so the next request sees exactly what the
+        // model would have seen had it made these calls itself.
+        for tool_result in &tool_results {
+            conversation_log.push(serde_json::json!({
+                "role": "tool",
+                "tool_call_id": tool_result["tool_call_id"],
+                "content": tool_result["content"]
+            }));
+            conversation.push(ResponseItem::FunctionCallOutput {
+                call_id: tool_result["tool_call_id"]
+                    .as_str()
+                    .unwrap_or("unknown")
+                    .to_string(),
+                output: codex_protocol::models::FunctionCallOutputPayload {
+                    content: tool_result["content"]
+                        .as_str()
+                        .map(|s| s.to_string())
+                        .unwrap_or_else(|| tool_result["content"].to_string()),
+                    success: None,
+                },
+            });
+        }
+
+        if finished {
+            return Ok((supervisor_message, true));
+        }
+    }
+
+    Err(anyhow::anyhow!(
+        "Driver model '{}' kept calling tools past the {}-step limit without returning a final instruction",
+        driver_model,
+        MAX_TOOL_STEPS
+    ))
+}
+
+/// Slack attachment bar color for a vulnerability's severity. Block Kit
+/// blocks alone have no color concept, so the report is still wrapped in a
+/// single classic `attachments` entry just to get this.
+fn severity_color(severity: &str) -> &'static str {
+    match severity.to_lowercase().as_str() {
+        "critical" => "#d93025",
+        "high" => "#e06666",
+        "medium" => "#f1c232",
+        "low" => "#93c47d",
+        _ => "#cccccc",
+    }
+}
+
+/// Build the Slack Block Kit payload for a vulnerability report: a header
+/// block with `title`, a fields section for `asset`/`vuln_type`/`severity`,
+/// and a formatted section per `description`/`repro_steps`/`impact`/
+/// `cleanup`, colored by [`severity_color`].
+fn build_slack_report_payload(
+    title: &str,
+    asset: &str,
+    vuln_type: &str,
+    severity: &str,
+    description: &str,
+    repro_steps: &str,
+    impact: &str,
+    cleanup: &str,
+) -> serde_json::Value {
+    let blocks = serde_json::json!([
+        {
+            "type": "header",
+            "text": { "type": "plain_text", "text": title, "emoji": true }
+        },
+        {
+            "type": "section",
+            "fields": [
+                { "type": "mrkdwn", "text": format!("*Asset:*\n{}", asset) },
+                { "type": "mrkdwn", "text": format!("*Type:*\n{}", vuln_type) },
+                { "type": "mrkdwn", "text": format!("*Severity:*\n{}", severity) }
+            ]
+        },
+        { "type": "divider" },
+        {
+            "type": "section",
+            "text": { "type": "mrkdwn", "text": format!("*Description*\n{}", description) }
+        },
+        {
+            "type": "section",
+            "text": { "type": "mrkdwn", "text": format!("*Reproduction Steps*\n{}", repro_steps) }
+        },
+        {
+            "type": "section",
+            "text": { "type": "mrkdwn", "text": format!("*Impact*\n{}", impact) }
+        },
+        {
+            "type": "section",
+            "text": { "type": "mrkdwn", "text": format!("*Cleanup*\n{}", cleanup) }
+        }
+    ]);
+
+    serde_json::json!({
+        "attachments": [
+            {
+                "color": severity_color(severity),
+                "blocks": blocks
+            }
+        ]
+    })
+}
+
+/// Timeout for a single Slack webhook request attempt.
+const SLACK_WEBHOOK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+/// Maximum attempts (including the first) before giving up on a 429/5xx.
+const SLACK_WEBHOOK_MAX_RETRIES: u32 = 3;
+
+/// POST a Slack Block Kit payload, retrying with exponential backoff on
+/// 429/5xx (Slack's documented transient-failure codes) or a failed request,
+/// for at most `SLACK_WEBHOOK_MAX_RETRIES` attempts in all. Returns the final status code and
+/// response body instead of leaking raw curl stdout/stderr back to the
+/// model.
+async fn post_slack_report(
+    webhook_url: &str,
+    payload: &serde_json::Value,
+) -> anyhow::Result<(u16, String)> {
+    let client = reqwest::Client::builder()
+        .timeout(SLACK_WEBHOOK_TIMEOUT)
+        .build()
+        .with_context(|| "Failed to build Slack webhook HTTP client")?;
+
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        match client.post(webhook_url).json(payload).send().await {
+            Ok(response) => {
+                let status = response.status();
+                let body = response.text().await.unwrap_or_default();
+                if (status.as_u16() == 429 || status.is_server_error())
+                    && attempt < SLACK_WEBHOOK_MAX_RETRIES
+                {
+                    let backoff = std::time::Duration::from_millis(500 * 2u64.pow(attempt - 1));
+                    println!(
+                        "⚠️  Slack webhook returned {} (attempt {}/{}), retrying in {:?}",
+                        status, attempt, SLACK_WEBHOOK_MAX_RETRIES, backoff
+                    );
+                    tokio::time::sleep(backoff).await;
+                    continue;
+                }
+                return Ok((status.as_u16(), body));
+            }
+            Err(e) if attempt < SLACK_WEBHOOK_MAX_RETRIES => {
+                let backoff = std::time::Duration::from_millis(500 * 2u64.pow(attempt - 1));
+                println!(
+                    "⚠️  Slack webhook request failed ({}) on attempt {}/{}, retrying in {:?}",
+                    e, attempt, SLACK_WEBHOOK_MAX_RETRIES, backoff
+                );
+                tokio::time::sleep(backoff).await;
+            }
+            Err(e) => return Err(anyhow::anyhow!("Slack webhook request failed: {}", e)),
+        }
+    }
+}
+
+/// Slack (and most chat webhooks) truncate or reject overly long block
+/// text, so cap anything we embed at a conservative character count well
+/// under Slack's ~3000-character section limit.
+const CRASH_REPORT_BACKTRACE_CHAR_LIMIT: usize = 2500;
+
+fn truncate_for_slack(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        text.to_string()
+    } else {
+        let truncated: String = text.chars().take(max_chars).collect();
+        format!("{}\n... (truncated)", truncated)
+    }
+}
+
+/// Best-effort demangling of any Itanium-mangled (`_Z...`) symbols found in
+/// raw backtrace text, so operators see readable Rust paths instead of
+/// compiler-mangled noise. Lines that aren't symbol names pass through
+/// unchanged.
+fn demangle_backtrace_text(raw: &str) -> String {
+    raw.lines()
+        .map(|line| {
+            line.split_whitespace()
+                .map(|token| {
+                    let trimmed = token.trim_matches(|c: char| !c.is_alphanumeric() && c != '_');
+                    if trimmed.starts_with("_Z") || trimmed.starts_with("__Z") {
+                        token.replace(trimmed, &rustc_demangle::demangle(trimmed).to_string())
+                    } else {
+                        token.to_string()
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
 
-        // Add function calls
-        for tool_call in &tool_calls {
-            conversation.push(ResponseItem::FunctionCall {
-                id: None,
-                name: tool_call["function"]["name"]
-                    .as_str()
-                    .unwrap_or("unknown")
-                    .to_string(),
-                arguments: serde_json::to_string(&tool_call["function"]["arguments"])
-                    .unwrap_or("{}".to_string()),
-                call_id: tool_call["id"].as_str().unwrap_or("unknown").to_string(),
-            });
-        }
+/// Structured crash report for a supervisor session that bubbled up an
+/// `anyhow::Error`, meant to be both human-readable (posted to Slack) and
+/// machine-readable (written to `session_logs_dir` as JSON).
+#[derive(Debug, Clone, serde::Serialize)]
+struct CrashReport {
+    timestamp: String,
+    error: String,
+    backtrace: String,
+    workload_name: Option<String>,
+    step_index: Option<u32>,
+    last_tool_call: Option<String>,
+}
 
-        // Add tool results
-        for tool_result in &tool_results {
-            conversation.push(ResponseItem::FunctionCallOutput {
-                call_id: tool_result["tool_call_id"]
-                    .as_str()
-                    .unwrap_or("unknown")
-                    .to_string(),
-                output: FunctionCallOutputPayload {
-                    content: tool_result["content"].as_str().unwrap_or("").to_string(),
-                    success: Some(true),
-                },
-            });
+fn build_crash_report_payload(report: &CrashReport) -> serde_json::Value {
+    let mut context_parts = vec![format!("*Time:*\n{}", report.timestamp)];
+    if let Some(workload_name) = &report.workload_name {
+        context_parts.push(format!("*Workload:*\n{}", workload_name));
+    }
+    if let Some(step_index) = report.step_index {
+        context_parts.push(format!("*Step:*\n{}", step_index));
+    }
+    if let Some(last_tool_call) = &report.last_tool_call {
+        context_parts.push(format!("*Last tool call:*\n{}", last_tool_call));
+    }
+    let context_fields: Vec<serde_json::Value> = context_parts
+        .into_iter()
+        .map(|text| serde_json::json!({ "type": "mrkdwn", "text": text }))
+        .collect();
+
+    let blocks = serde_json::json!([
+        {
+            "type": "header",
+            "text": { "type": "plain_text", "text": "🚨 Autonomous session crashed", "emoji": true }
+        },
+        { "type": "section", "fields": context_fields },
+        { "type": "divider" },
+        {
+            "type": "section",
+            "text": { "type": "mrkdwn", "text": format!("*Error*\n{}", report.error) }
+        },
+        {
+            "type": "section",
+            "text": {
+                "type": "mrkdwn",
+                "text": format!(
+                    "*Backtrace*\n```{}```",
+                    truncate_for_slack(&report.backtrace, CRASH_REPORT_BACKTRACE_CHAR_LIMIT)
+                )
+            }
         }
+    ]);
 
-        // Make another call to get the follow-up instruction
-        let follow_up_prompt = Prompt {
-            input: conversation,
-            store: false,
-            tools: vec![], // No tools for follow-up
-            base_instructions_override: None,
-        };
-
-        let mut follow_up_stream = client
-            .stream(&follow_up_prompt)
-            .await
-            .with_context(|| "Failed to create follow-up response stream")?;
+    serde_json::json!({
+        "attachments": [
+            { "color": severity_color("high"), "blocks": blocks }
+        ]
+    })
+}
 
-        let mut follow_up_text = String::new();
+/// Demangle and ship a structured crash report for `error` so operators get
+/// actionable failure context instead of a silent process exit. Uploading
+/// is opt-in via `CRASH_REPORT_UPLOAD` (since a crash can happen
+/// mid-engagement and operators may not want every failure paged to Slack);
+/// when upload isn't enabled, isn't configured, or itself fails, the report
+/// is written to `session_logs_dir` instead so nothing is lost.
+async fn report_session_crash(
+    error: &anyhow::Error,
+    session_logs_dir: &std::path::Path,
+    workload_name: Option<&str>,
+    step_index: Option<u32>,
+    last_tool_call: Option<&str>,
+) {
+    // `error.backtrace()` is the backtrace `anyhow` captured at the point
+    // the error was created (when `RUST_BACKTRACE`/`RUST_LIB_BACKTRACE` is
+    // set), which is the actual failing call chain. Capturing a fresh
+    // backtrace here instead would only show this function's own call
+    // stack up through the `?` chain that propagated the error, which by
+    // the time a session-ending error reaches this reporting site is just
+    // `cli_main`/the tokio runtime — not the frames anyone debugging the
+    // crash actually needs.
+    let raw_backtrace = error.backtrace().to_string();
+    let report = CrashReport {
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        error: error.to_string(),
+        backtrace: demangle_backtrace_text(&raw_backtrace),
+        workload_name: workload_name.map(|s| s.to_string()),
+        step_index,
+        last_tool_call: last_tool_call.map(|s| s.to_string()),
+    };
 
-        // Collect follow-up response
-        while let Some(event) = follow_up_stream.next().await {
-            match event {
-                Ok(response_event) => match response_event {
-                    codex_core::client_common::ResponseEvent::OutputItemDone(item) => match item {
-                        ResponseItem::Message { content, .. } => {
-                            for content_item in content {
-                                match content_item {
-                                    ContentItem::OutputText { text } => {
-                                        follow_up_text.push_str(&text);
-                                    }
-                                    _ => {}
-                                }
-                            }
-                        }
-                        _ => {}
-                    },
-                    codex_core::client_common::ResponseEvent::Completed { .. } => {
-                        break;
-                    }
-                    _ => {}
-                },
+    let upload_enabled = std::env::var("CRASH_REPORT_UPLOAD")
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+    let webhook_url = std::env::var("CRASH_REPORT_WEBHOOK_URL")
+        .ok()
+        .or_else(|| std::env::var("SLACK_WEBHOOK_URL").ok());
+
+    let uploaded = if upload_enabled {
+        if let Some(url) = &webhook_url {
+            let payload = build_crash_report_payload(&report);
+            match post_slack_report(url, &payload).await {
+                Ok((status, _)) if (200..300).contains(&status) => true,
+                Ok((status, body)) => {
+                    eprintln!(
+                        "❌ Crash report upload returned status {}: {}",
+                        status, body
+                    );
+                    false
+                }
                 Err(e) => {
-                    return Err(anyhow::anyhow!("Error in follow-up response stream: {}", e));
+                    eprintln!("❌ Failed to upload crash report: {}", e);
+                    false
                 }
             }
+        } else {
+            eprintln!("⚠️  CRASH_REPORT_UPLOAD is set but no webhook URL is configured (CRASH_REPORT_WEBHOOK_URL / SLACK_WEBHOOK_URL); falling back to a local report");
+            false
         }
+    } else {
+        false
+    };
 
-        return Ok((follow_up_text.trim().to_string(), tool_results));
+    if !uploaded {
+        let report_path = session_logs_dir.join(format!(
+            "crash_report_{}.json",
+            chrono::Utc::now().format("%Y%m%d_%H%M%S")
+        ));
+        match serde_json::to_string_pretty(&report) {
+            Ok(json) => {
+                if let Err(e) = std::fs::write(&report_path, &json) {
+                    eprintln!("❌ Failed to write crash report to {:?}: {}", report_path, e);
+                } else {
+                    println!("📄 Wrote crash report to {:?}", report_path);
+                }
+            }
+            Err(e) => eprintln!("❌ Failed to serialize crash report: {}", e),
+        }
     }
+}
 
-    if response_text.is_empty() {
-        return Err(anyhow::anyhow!("No response received from external LLM"));
+/// Required fields and per-field JSON Schema types for a supervisor tool's
+/// arguments, used to reject malformed tool calls before they execute.
+#[derive(Debug, Clone)]
+struct ToolArgumentSchema {
+    required: Vec<String>,
+    properties: serde_json::Value,
+}
+
+/// Argument schemas for the built-in supervisor tools (note-taking, Slack,
+/// and session control), kept in sync with the `ToolInputSchema`s registered
+/// in `generate_driver_response`'s `extra_tools` map.
+fn builtin_tool_argument_schemas() -> std::collections::HashMap<&'static str, ToolArgumentSchema> {
+    let mut schemas = std::collections::HashMap::new();
+    schemas.insert(
+        "write_note",
+        ToolArgumentSchema {
+            required: vec!["content".to_string()],
+            properties: serde_json::json!({ "content": { "type": "string" } }),
+        },
+    );
+    schemas.insert(
+        "read_notes",
+        ToolArgumentSchema {
+            required: vec![],
+            properties: serde_json::json!({}),
+        },
+    );
+    schemas.insert(
+        "slack_webhook",
+        ToolArgumentSchema {
+            required: vec![
+                "title".to_string(),
+                "asset".to_string(),
+                "vuln_type".to_string(),
+                "severity".to_string(),
+                "description".to_string(),
+                "repro_steps".to_string(),
+                "impact".to_string(),
+                "cleanup".to_string(),
+            ],
+            properties: serde_json::json!({
+                "title": { "type": "string" },
+                "asset": { "type": "string" },
+                "vuln_type": { "type": "string" },
+                "severity": { "type": "string" },
+                "description": { "type": "string" },
+                "repro_steps": { "type": "string" },
+                "impact": { "type": "string" },
+                "cleanup": { "type": "string" }
+            }),
+        },
+    );
+    schemas.insert(
+        "finished",
+        ToolArgumentSchema {
+            required: vec!["reason".to_string()],
+            properties: serde_json::json!({ "reason": { "type": "string" } }),
+        },
+    );
+    schemas
+}
+
+/// `generate_driver_response` threads a `FunctionCall`'s `arguments` through
+/// exactly as the model/API returned them: a raw JSON-encoded string, never
+/// parsed into an object. Indexing straight into that `Value::String` (e.g.
+/// `arguments["content"]`) always misses, so anything that needs to inspect
+/// individual fields — validation and the tool arms below — decodes it into
+/// a real `Value` with this first. A value that isn't a string of valid JSON
+/// is returned unchanged.
+fn parse_tool_call_arguments(arguments: &serde_json::Value) -> serde_json::Value {
+    arguments
+        .as_str()
+        .and_then(|raw| serde_json::from_str(raw).ok())
+        .unwrap_or_else(|| arguments.clone())
+}
+
+/// Check `arguments` against `schema`'s `required` list and each declared
+/// property's `type`, returning a descriptive error for the first problem
+/// found (missing required field, or a field present with the wrong JSON
+/// type) instead of letting a tool arm silently default it away.
+fn validate_tool_arguments(
+    tool_name: &str,
+    arguments: &serde_json::Value,
+    schema: &ToolArgumentSchema,
+) -> Result<(), String> {
+    let object = arguments.as_object();
+
+    for field in &schema.required {
+        let present = object
+            .and_then(|obj| obj.get(field))
+            .map(|v| !v.is_null())
+            .unwrap_or(false);
+        if !present {
+            return Err(format!(
+                "Tool call '{}' is invalid: missing required field '{}'",
+                tool_name, field
+            ));
+        }
+    }
+
+    if let (Some(object), Some(properties)) = (object, schema.properties.as_object()) {
+        for (field, value) in object {
+            let Some(expected_type) = properties
+                .get(field)
+                .and_then(|prop| prop.get("type"))
+                .and_then(|t| t.as_str())
+            else {
+                continue;
+            };
+            let matches_type = match expected_type {
+                "string" => value.is_string(),
+                "number" => value.is_number(),
+                "integer" => value.is_i64() || value.is_u64(),
+                "boolean" => value.is_boolean(),
+                "object" => value.is_object(),
+                "array" => value.is_array(),
+                _ => true,
+            };
+            if !matches_type {
+                return Err(format!(
+                    "Tool call '{}' is invalid: field '{}' should be of type '{}'",
+                    tool_name, field, expected_type
+                ));
+            }
+        }
     }
 
-    Ok((response_text.trim().to_string(), Vec::new()))
+    Ok(())
 }
 
+/// Run every tool call the driver model made in one step concurrently
+/// instead of strictly sequentially, so e.g. an independent `read_notes`
+/// and `slack_webhook` call in the same turn don't block each other.
+/// `plugin_registry` is the only state every call might need exclusively
+/// (invoking a plugin writes to its child process over stdin/stdout), so
+/// it's wrapped in an async mutex here: plugin tool calls serialize against
+/// each other, but everything else still runs fully in parallel.
+/// `join_all` polls every call's future to completion while preserving
+/// input order in its output, so `tool_results` naturally lines up with
+/// `tool_calls` without any extra indexing.
 async fn handle_supervisor_tool_calls(
     tool_calls: &[serde_json::Value],
     session_logs_dir: &std::path::Path,
+    plugin_registry: &mut PluginRegistry,
+    tool_policy_table: &std::collections::HashMap<String, ToolPolicyAction>,
+    tool_schema_table: &std::collections::HashMap<String, ToolArgumentSchema>,
+    driver_model: &str,
+    bugcrowd_approval_prompt_template: &str,
+    hook_table: &std::collections::HashMap<String, Vec<HookConfig>>,
+    observability_hub: Option<&ObservabilityHub>,
+    config_content: &str,
+    remote_approval_timeout: std::time::Duration,
+    tool_confirmation_mode: ToolConfirmationMode,
 ) -> anyhow::Result<(Vec<serde_json::Value>, bool)> {
-    let mut tool_results = Vec::new();
-    let mut session_finished = false;
     let notes_dir = session_logs_dir.join("notes");
-
-    // Ensure notes directory exists
     std::fs::create_dir_all(&notes_dir).with_context(|| "Failed to create notes directory")?;
 
-    for tool_call in tool_calls {
+    let plugin_registry = tokio::sync::Mutex::new(plugin_registry);
+    let note_counter = std::sync::atomic::AtomicU64::new(0);
+
+    let results = futures::future::join_all(tool_calls.iter().map(|tool_call| {
+        handle_one_supervisor_tool_call(
+            tool_call,
+            session_logs_dir,
+            &notes_dir,
+            &note_counter,
+            &plugin_registry,
+            tool_policy_table,
+            tool_schema_table,
+            driver_model,
+            bugcrowd_approval_prompt_template,
+            hook_table,
+            observability_hub,
+            config_content,
+            remote_approval_timeout,
+            tool_confirmation_mode,
+        )
+    }))
+    .await;
+
+    let session_finished = results.iter().any(|(_, finished)| *finished);
+    let tool_results = results.into_iter().map(|(result, _)| result).collect();
+    Ok((tool_results, session_finished))
+}
+
+/// Execute a single supervisor tool call and report whether it was the
+/// `finished` tool, i.e. whether the driver session should end. Split out of
+/// [`handle_supervisor_tool_calls`] so that function can run every call
+/// through this one concurrently via `join_all`.
+#[allow(clippy::too_many_arguments)]
+async fn handle_one_supervisor_tool_call(
+    tool_call: &serde_json::Value,
+    session_logs_dir: &std::path::Path,
+    notes_dir: &std::path::Path,
+    note_counter: &std::sync::atomic::AtomicU64,
+    plugin_registry: &tokio::sync::Mutex<&mut PluginRegistry>,
+    tool_policy_table: &std::collections::HashMap<String, ToolPolicyAction>,
+    tool_schema_table: &std::collections::HashMap<String, ToolArgumentSchema>,
+    driver_model: &str,
+    bugcrowd_approval_prompt_template: &str,
+    hook_table: &std::collections::HashMap<String, Vec<HookConfig>>,
+    observability_hub: Option<&ObservabilityHub>,
+    config_content: &str,
+    remote_approval_timeout: std::time::Duration,
+    tool_confirmation_mode: ToolConfirmationMode,
+) -> (serde_json::Value, bool) {
+    let mut tool_results = Vec::new();
+    let mut session_finished = false;
+
+    {
         let tool_id = tool_call["id"].as_str().unwrap_or("unknown");
         let tool_name = tool_call["function"]["name"].as_str().unwrap_or("unknown");
         let arguments = &tool_call["function"]["arguments"];
@@ -1942,14 +5960,141 @@ async fn handle_supervisor_tool_calls(
             "🔧 Debug tool_call structure: {}",
             serde_json::to_string_pretty(&tool_call).unwrap_or("invalid".to_string())
         );
+
+        // Reject malformed arguments before they ever reach a tool arm:
+        // `arguments["field"].as_str().unwrap_or("")` elsewhere in this
+        // function would otherwise silently coerce a missing/mistyped field
+        // to an empty string (e.g. posting a Slack report with a blank
+        // severity) instead of letting the model see and correct its mistake.
+        // `arguments` itself is the raw JSON-encoded string the API returned
+        // (never parsed further up in `generate_driver_response`), so it has
+        // to be decoded into a real object before it can be validated.
+        let parsed_arguments = parse_tool_call_arguments(arguments);
+        if let Some(schema) = tool_schema_table.get(tool_name) {
+            if let Err(message) = validate_tool_arguments(tool_name, &parsed_arguments, schema) {
+                println!("🚫 Rejecting malformed tool call: {}", message);
+                run_lifecycle_hooks(
+                    hook_table,
+                    "tool_denied",
+                    &serde_json::json!({ "tool": tool_name, "reason": &message }),
+                    session_logs_dir,
+                );
+                return (
+                    serde_json::json!({
+                        "tool_call_id": tool_id,
+                        "tool_name": tool_name,
+                        "content": format!("❌ {}", message)
+                    }),
+                    false,
+                );
+            }
+        }
+
+        let vetoed = run_lifecycle_hooks(
+            hook_table,
+            "tool_call_begin",
+            &serde_json::json!({
+                "tool": tool_name,
+                "arguments": arguments,
+            }),
+            session_logs_dir,
+        );
+        if vetoed {
+            println!("🚫 Denying '{}' per tool_call_begin hook veto", tool_name);
+            run_lifecycle_hooks(
+                hook_table,
+                "tool_denied",
+                &serde_json::json!({
+                    "tool": tool_name,
+                    "reason": "vetoed by tool_call_begin hook",
+                }),
+                session_logs_dir,
+            );
+            return (
+                serde_json::json!({
+                    "tool_call_id": tool_id,
+                    "tool_name": tool_name,
+                    "content": format!("❌ '{}' denied by lifecycle hook", tool_name)
+                }),
+                false,
+            );
+        }
+
+        if is_side_effecting_tool(tool_name) {
+            match tool_confirmation_mode {
+                ToolConfirmationMode::Auto => {}
+                ToolConfirmationMode::ReadOnly => {
+                    println!(
+                        "🚫 Denying side-effecting tool '{}' per read-only confirmation mode",
+                        tool_name
+                    );
+                    run_lifecycle_hooks(
+                        hook_table,
+                        "tool_denied",
+                        &serde_json::json!({
+                            "tool": tool_name,
+                            "reason": "read-only confirmation mode",
+                        }),
+                        session_logs_dir,
+                    );
+                    return (
+                        serde_json::json!({
+                            "tool_call_id": tool_id,
+                            "tool_name": tool_name,
+                            "content": format!(
+                                "❌ '{}' denied: session is running in read-only confirmation mode",
+                                tool_name
+                            )
+                        }),
+                        false,
+                    );
+                }
+                ToolConfirmationMode::Interactive => {
+                    let prompt_tool_name = tool_name.to_string();
+                    let prompt_arguments = parsed_arguments.clone();
+                    let approved = tokio::task::spawn_blocking(move || {
+                        prompt_operator_confirmation(&prompt_tool_name, &prompt_arguments)
+                    })
+                    .await
+                    .unwrap_or(false);
+                    if !approved {
+                        println!("🚫 Operator denied side-effecting tool '{}'", tool_name);
+                        run_lifecycle_hooks(
+                            hook_table,
+                            "tool_denied",
+                            &serde_json::json!({
+                                "tool": tool_name,
+                                "reason": "denied by operator",
+                            }),
+                            session_logs_dir,
+                        );
+                        return (
+                            serde_json::json!({
+                                "tool_call_id": tool_id,
+                                "tool_name": tool_name,
+                                "content": format!("❌ '{}' denied by operator", tool_name)
+                            }),
+                            false,
+                        );
+                    }
+                }
+            }
+        }
+
         match tool_name {
             "write_note" => {
-                let content = arguments["content"].as_str().unwrap_or("");
+                let content = parsed_arguments["content"].as_str().unwrap_or("");
                 let timestamp = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC");
                 let note_content = format!("[{}] {}\n", timestamp, content);
 
-                // Generate a timestamped filename
-                let filename = format!("note_{}.txt", chrono::Utc::now().format("%Y%m%d_%H%M%S"));
+                // Timestamp plus a monotonic counter keeps filenames unique even
+                // when two `write_note` calls land in the same second.
+                let counter = note_counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let filename = format!(
+                    "note_{}_{}.txt",
+                    chrono::Utc::now().format("%Y%m%d_%H%M%S"),
+                    counter
+                );
                 let note_path = notes_dir.join(&filename);
 
                 match std::fs::write(&note_path, &note_content) {
@@ -2025,65 +6170,53 @@ async fn handle_supervisor_tool_calls(
                 println!("📖 Supervisor read notes");
             }
             "slack_webhook" => {
-                // Build vulnerability report JSON and post to Slack webhook
-                let title = arguments["title"].as_str().unwrap_or("");
-                let asset = arguments["asset"].as_str().unwrap_or("");
-                let vuln_type = arguments["vuln_type"].as_str().unwrap_or("");
-                let severity = arguments["severity"].as_str().unwrap_or("");
-                let description = arguments["description"].as_str().unwrap_or("");
-                let repro_steps = arguments["repro_steps"].as_str().unwrap_or("");
-                let impact = arguments["impact"].as_str().unwrap_or("");
-                let cleanup = arguments["cleanup"].as_str().unwrap_or("");
-
-                let payload = serde_json::json!({
-                    "title": title,
-                    "asset": asset,
-                    "vuln_type": vuln_type,
-                    "severity": severity,
-                    "description": description,
-                    "repro_steps": repro_steps,
-                    "impact": impact,
-                    "cleanup": cleanup
-                });
-                let payload_str = payload.to_string();
+                let title = parsed_arguments["title"].as_str().unwrap_or("");
+                let asset = parsed_arguments["asset"].as_str().unwrap_or("");
+                let vuln_type = parsed_arguments["vuln_type"].as_str().unwrap_or("");
+                let severity = parsed_arguments["severity"].as_str().unwrap_or("");
+                let description = parsed_arguments["description"].as_str().unwrap_or("");
+                let repro_steps = parsed_arguments["repro_steps"].as_str().unwrap_or("");
+                let impact = parsed_arguments["impact"].as_str().unwrap_or("");
+                let cleanup = parsed_arguments["cleanup"].as_str().unwrap_or("");
+
+                let payload = build_slack_report_payload(
+                    title,
+                    asset,
+                    vuln_type,
+                    severity,
+                    description,
+                    repro_steps,
+                    impact,
+                    cleanup,
+                );
 
                 match std::env::var("SLACK_WEBHOOK_URL") {
-                    Ok(webhook_url) => {
-                        match std::process::Command::new("curl")
-                            .args(&[
-                                "-X",
-                                "POST",
-                                "-H",
-                                "Content-Type: application/json",
-                                "--data",
-                                &payload_str,
-                                &webhook_url,
-                            ])
-                            .output()
-                        {
-                            Ok(output) => {
-                                let stdout = String::from_utf8_lossy(&output.stdout);
-                                let stderr = String::from_utf8_lossy(&output.stderr);
-                                tool_results.push(serde_json::json!({
-                                    "tool_call_id": tool_id,
-                                    "tool_name": tool_name,
-                                    "content": format!(
-                                        "Slack webhook posted: stdout={}, stderr={}",
-                                        stdout, stderr
-                                    )
-                                }));
-                                println!("✅ Slack report sent");
-                            }
-                            Err(e) => {
-                                tool_results.push(serde_json::json!({
-                                    "tool_call_id": tool_id,
-                                    "tool_name": tool_name,
-                                    "content": format!("Error posting to Slack webhook: {}", e)
-                                }));
-                                println!("❌ Failed to send Slack report: {}", e);
-                            }
+                    Ok(webhook_url) => match post_slack_report(&webhook_url, &payload).await {
+                        Ok((status, body)) if (200..300).contains(&status) => {
+                            tool_results.push(serde_json::json!({
+                                "tool_call_id": tool_id,
+                                "tool_name": tool_name,
+                                "content": format!("Slack webhook posted successfully (status {})", status)
+                            }));
+                            println!("✅ Slack report sent (status {}): {}", status, body);
                         }
-                    }
+                        Ok((status, body)) => {
+                            tool_results.push(serde_json::json!({
+                                "tool_call_id": tool_id,
+                                "tool_name": tool_name,
+                                "content": format!("Slack webhook returned status {}: {}", status, body)
+                            }));
+                            println!("❌ Slack webhook returned status {}: {}", status, body);
+                        }
+                        Err(e) => {
+                            tool_results.push(serde_json::json!({
+                                "tool_call_id": tool_id,
+                                "tool_name": tool_name,
+                                "content": format!("Error posting to Slack webhook: {}", e)
+                            }));
+                            println!("❌ Failed to send Slack report: {}", e);
+                        }
+                    },
                     Err(_) => {
                         tool_results.push(serde_json::json!({
                             "tool_call_id": tool_id,
@@ -2095,7 +6228,7 @@ async fn handle_supervisor_tool_calls(
                 }
             }
             "finished" => {
-                let reason = arguments["reason"].as_str().unwrap_or("No reason provided");
+                let reason = parsed_arguments["reason"].as_str().unwrap_or("No reason provided");
                 println!("🏁 Session finished by driver model: {}", reason);
 
                 tool_results.push(serde_json::json!({
@@ -2106,6 +6239,102 @@ async fn handle_supervisor_tool_calls(
 
                 session_finished = true;
             }
+            other if is_plugin_tool(other) => {
+                // Plugin tools go through the same approval-policy table as
+                // codex's own MCP tools, just applied here instead of in
+                // `collect_codex_response_with_tools` since plugin calls
+                // never reach codex at all.
+                match resolve_tool_policy(tool_policy_table, other) {
+                    ToolPolicyAction::Deny => {
+                        println!("🚫 Denying plugin tool '{}' per tool policy", other);
+                        run_lifecycle_hooks(
+                            hook_table,
+                            "tool_denied",
+                            &serde_json::json!({ "tool": other, "reason": "tool policy" }),
+                            session_logs_dir,
+                        );
+                        tool_results.push(serde_json::json!({
+                            "tool_call_id": tool_id,
+                            "tool_name": tool_name,
+                            "content": format!("❌ '{}' denied by tool policy", other)
+                        }));
+                    }
+                    ToolPolicyAction::RequireLlmApproval => {
+                        println!(
+                            "🤖 Requesting approval from external LLM for plugin tool '{}'...",
+                            other
+                        );
+                        let approval_prompt = inject_bugcrowd_approval_variables(
+                            bugcrowd_approval_prompt_template,
+                            other,
+                            &Some(parsed_arguments.clone()),
+                        );
+                        match resolve_llm_approval(
+                            observability_hub,
+                            tool_id,
+                            &approval_prompt,
+                            driver_model,
+                            session_logs_dir,
+                            config_content,
+                            "plugin_tool",
+                            remote_approval_timeout,
+                        )
+                        .await
+                        {
+                            Ok((approved, reasoning, source)) => {
+                                if approved {
+                                    run_lifecycle_hooks(
+                                        hook_table,
+                                        "bugcrowd_submit_approved",
+                                        &serde_json::json!({
+                                            "tool": other,
+                                            "reasoning": reasoning,
+                                            "decided_by": source.to_string(),
+                                        }),
+                                        session_logs_dir,
+                                    );
+                                    let mut guard = plugin_registry.lock().await;
+                                    tool_results.push(
+                                        run_plugin_tool(&mut guard, other, tool_id, tool_name, &parsed_arguments)
+                                            .await,
+                                    );
+                                } else {
+                                    println!("❌ Plugin tool '{}' denied by {}: {}", other, source, reasoning);
+                                    run_lifecycle_hooks(
+                                        hook_table,
+                                        "tool_denied",
+                                        &serde_json::json!({
+                                            "tool": other,
+                                            "reason": reasoning,
+                                            "decided_by": source.to_string(),
+                                        }),
+                                        session_logs_dir,
+                                    );
+                                    tool_results.push(serde_json::json!({
+                                        "tool_call_id": tool_id,
+                                        "tool_name": tool_name,
+                                        "decided_by": source.to_string(),
+                                        "content": format!("❌ '{}' denied by security review: {}", other, reasoning)
+                                    }));
+                                }
+                            }
+                            Err(e) => {
+                                tool_results.push(serde_json::json!({
+                                    "tool_call_id": tool_id,
+                                    "tool_name": tool_name,
+                                    "content": format!("❌ '{}' call failed due to approval error: {}", other, e)
+                                }));
+                            }
+                        }
+                    }
+                    ToolPolicyAction::AutoApprove => {
+                        let mut guard = plugin_registry.lock().await;
+                        tool_results.push(
+                            run_plugin_tool(&mut guard, other, tool_id, tool_name, &parsed_arguments).await,
+                        );
+                    }
+                }
+            }
             _ => {
                 tool_results.push(serde_json::json!({
                     "tool_call_id": tool_id,
@@ -2116,7 +6345,44 @@ async fn handle_supervisor_tool_calls(
         }
     }
 
-    Ok((tool_results, session_finished))
+    (
+        tool_results
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| serde_json::json!({ "content": "no result produced" })),
+        session_finished,
+    )
+}
+
+/// Invoke a plugin tool and shape its result into the same
+/// `{tool_call_id, tool_name, content}` payload the built-in tool arms
+/// produce, so plugin tools are indistinguishable from built-in ones once
+/// they reach `conversation_log`.
+async fn run_plugin_tool(
+    plugin_registry: &mut PluginRegistry,
+    namespaced_name: &str,
+    tool_id: &str,
+    tool_name: &str,
+    arguments: &serde_json::Value,
+) -> serde_json::Value {
+    match plugin_registry.invoke(namespaced_name, arguments.clone()).await {
+        Ok(result) => {
+            println!("🔌 Plugin tool '{}' returned a result", namespaced_name);
+            serde_json::json!({
+                "tool_call_id": tool_id,
+                "tool_name": tool_name,
+                "content": result
+            })
+        }
+        Err(e) => {
+            println!("❌ Plugin tool '{}' failed: {}", namespaced_name, e);
+            serde_json::json!({
+                "tool_call_id": tool_id,
+                "tool_name": tool_name,
+                "content": format!("❌ '{}' call failed: {}", namespaced_name, e)
+            })
+        }
+    }
 }
 
 /// Prepend root-level overrides so they have lower precedence than
@@ -2135,3 +6401,325 @@ fn print_completion(cmd: CompletionCommand) {
     let name = "codex";
     generate(cmd.shell, &mut app, name, &mut std::io::stdout());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A turn with several tool calls in one message must never be split by
+    /// `compute_compaction_plan`: the boundary it returns has to land after
+    /// every one of that turn's `"tool"` responses, never in between.
+    #[test]
+    fn compute_compaction_plan_keeps_multi_tool_call_turns_intact() {
+        let mut conversation_log = vec![serde_json::json!({
+            "role": "system",
+            "content": "you are a helpful supervisor"
+        })];
+
+        // A handful of early single-call turns to pad out the token count.
+        for i in 0..20 {
+            conversation_log.push(serde_json::json!({
+                "role": "user",
+                "content": format!("padding prompt {}", i),
+                "tool_calls": [
+                    { "id": format!("call_{}", i), "type": "function", "function": { "name": "read_notes", "arguments": "{}" } }
+                ]
+            }));
+            conversation_log.push(serde_json::json!({
+                "role": "tool",
+                "tool_call_id": format!("call_{}", i),
+                "content": "no notes yet".repeat(50)
+            }));
+        }
+
+        // One turn with three tool calls whose responses must stay together.
+        conversation_log.push(serde_json::json!({
+            "role": "user",
+            "content": "multi-call turn",
+            "tool_calls": [
+                { "id": "multi_1", "type": "function", "function": { "name": "read_notes", "arguments": "{}" } },
+                { "id": "multi_2", "type": "function", "function": { "name": "read_notes", "arguments": "{}" } },
+                { "id": "multi_3", "type": "function", "function": { "name": "read_notes", "arguments": "{}" } }
+            ]
+        }));
+        conversation_log.push(serde_json::json!({
+            "role": "tool",
+            "tool_call_id": "multi_1",
+            "content": "result 1"
+        }));
+        conversation_log.push(serde_json::json!({
+            "role": "tool",
+            "tool_call_id": "multi_2",
+            "content": "result 2"
+        }));
+        conversation_log.push(serde_json::json!({
+            "role": "tool",
+            "tool_call_id": "multi_3",
+            "content": "result 3"
+        }));
+
+        for i in 0..20 {
+            conversation_log.push(serde_json::json!({
+                "role": "assistant",
+                "content": format!("trailing message {}", i)
+            }));
+        }
+
+        let plan = compute_compaction_plan(&conversation_log, 2000)
+            .expect("token counting should succeed")
+            .expect("this log should be over budget and trigger a compaction plan");
+
+        let boundary_entry = &conversation_log[plan.end];
+        assert_ne!(
+            boundary_entry.get("role").and_then(|r| r.as_str()),
+            Some("tool"),
+            "compaction boundary must not land on a tool response orphaned from its call"
+        );
+    }
+
+    #[test]
+    fn turn_end_at_skips_every_response_in_a_multi_call_turn() {
+        let conversation_log = vec![
+            serde_json::json!({
+                "role": "user",
+                "content": "multi-call turn",
+                "tool_calls": [
+                    { "id": "a", "type": "function", "function": { "name": "read_notes", "arguments": "{}" } },
+                    { "id": "b", "type": "function", "function": { "name": "read_notes", "arguments": "{}" } }
+                ]
+            }),
+            serde_json::json!({ "role": "tool", "tool_call_id": "a", "content": "result a" }),
+            serde_json::json!({ "role": "tool", "tool_call_id": "b", "content": "result b" }),
+            serde_json::json!({ "role": "assistant", "content": "final" }),
+        ];
+
+        assert_eq!(turn_end_at(&conversation_log, 0), 3);
+    }
+
+    /// A real tool call arrives with `arguments` as the raw JSON-encoded
+    /// string the model returned, not a parsed object. Validation has to
+    /// decode it first or every call with required fields would be rejected
+    /// as "missing", even when every field is genuinely present.
+    #[test]
+    fn validate_tool_arguments_accepts_encoded_string_arguments() {
+        let schema = builtin_tool_argument_schemas()
+            .remove("slack_webhook")
+            .expect("slack_webhook has a built-in schema");
+
+        let encoded_arguments = serde_json::Value::String(
+            serde_json::json!({
+                "title": "SQL injection in login form",
+                "asset": "api.example.com",
+                "vuln_type": "SQL Injection",
+                "severity": "high",
+                "description": "The login form is vulnerable to SQLi.",
+                "repro_steps": "1. Submit ' OR 1=1-- as the username.",
+                "impact": "Full database read access.",
+                "cleanup": "No persistent changes were made."
+            })
+            .to_string(),
+        );
+
+        let parsed_arguments = parse_tool_call_arguments(&encoded_arguments);
+        assert!(validate_tool_arguments("slack_webhook", &parsed_arguments, &schema).is_ok());
+    }
+
+    #[test]
+    fn validate_tool_arguments_rejects_encoded_string_missing_required_field() {
+        let schema = builtin_tool_argument_schemas()
+            .remove("slack_webhook")
+            .expect("slack_webhook has a built-in schema");
+
+        let encoded_arguments = serde_json::Value::String(
+            serde_json::json!({
+                "title": "SQL injection in login form",
+                "asset": "api.example.com",
+                "vuln_type": "SQL Injection",
+                "description": "The login form is vulnerable to SQLi.",
+                "repro_steps": "1. Submit ' OR 1=1-- as the username.",
+                "impact": "Full database read access.",
+                "cleanup": "No persistent changes were made."
+            })
+            .to_string(),
+        );
+
+        let parsed_arguments = parse_tool_call_arguments(&encoded_arguments);
+        let result = validate_tool_arguments("slack_webhook", &parsed_arguments, &schema);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("severity"));
+    }
+
+    fn approval_fixture_scenario(
+        command_contains: &str,
+        stubbed_llm_response: Option<&str>,
+    ) -> ApprovalFixtureScenario {
+        ApprovalFixtureScenario {
+            name: "test scenario".to_string(),
+            command: command_contains.split(' ').map(String::from).collect(),
+            cwd: ".".to_string(),
+            stubbed_llm_response: stubbed_llm_response.map(String::from),
+            expected_decision: "approved".to_string(),
+        }
+    }
+
+    /// An `allow` approval hook wins outright, without ever falling through
+    /// to the tool policy table or an LLM decision.
+    #[tokio::test]
+    async fn replay_approval_scenario_allow_hook_wins() {
+        let scenario = approval_fixture_scenario("rm -rf /tmp/scratch", None);
+        let hooks = vec![ApprovalHookConfig {
+            name: "allow scratch cleanup".to_string(),
+            action: "allow".to_string(),
+            command_contains: Some("rm -rf /tmp/scratch".to_string()),
+            cwd_prefix: None,
+            changed_file_pattern: None,
+        }];
+        let tool_policy_table = std::collections::HashMap::new();
+
+        let approved = replay_approval_scenario(&scenario, &hooks, &tool_policy_table)
+            .expect("an allow hook resolves without touching the LLM path");
+        assert!(approved);
+    }
+
+    /// A `deny` approval hook wins outright, same as `allow`.
+    #[tokio::test]
+    async fn replay_approval_scenario_deny_hook_wins() {
+        let scenario = approval_fixture_scenario("rm -rf /", None);
+        let hooks = vec![ApprovalHookConfig {
+            name: "deny root wipe".to_string(),
+            action: "deny".to_string(),
+            command_contains: Some("rm -rf /".to_string()),
+            cwd_prefix: None,
+            changed_file_pattern: None,
+        }];
+        let tool_policy_table = std::collections::HashMap::new();
+
+        let approved = replay_approval_scenario(&scenario, &hooks, &tool_policy_table)
+            .expect("a deny hook resolves without touching the LLM path");
+        assert!(!approved);
+    }
+
+    /// With no matching hook, the tool policy table decides without ever
+    /// needing a stubbed LLM response.
+    #[tokio::test]
+    async fn replay_approval_scenario_falls_back_to_tool_policy_table() {
+        let scenario = approval_fixture_scenario("cat README.md", None);
+        let hooks = Vec::new();
+        let mut tool_policy_table = std::collections::HashMap::new();
+        tool_policy_table.insert("cat README.md".to_string(), ToolPolicyAction::AutoApprove);
+
+        let approved = replay_approval_scenario(&scenario, &hooks, &tool_policy_table)
+            .expect("an auto_approve tool policy resolves without touching the LLM path");
+        assert!(approved);
+    }
+
+    /// With neither a hook nor a tool policy deciding, the scenario's
+    /// `stubbed_llm_response` is replayed through `parse_approval_response`
+    /// exactly as the live pipeline would feed a real LLM reply to it.
+    #[tokio::test]
+    async fn replay_approval_scenario_replays_stubbed_llm_response() {
+        let approve_scenario =
+            approval_fixture_scenario("curl https://example.com", Some("APPROVE - looks safe"));
+        let deny_scenario =
+            approval_fixture_scenario("curl https://example.com", Some("DENY - exfil risk"));
+        let hooks = Vec::new();
+        let tool_policy_table = std::collections::HashMap::new();
+
+        assert!(replay_approval_scenario(&approve_scenario, &hooks, &tool_policy_table).unwrap());
+        assert!(!replay_approval_scenario(&deny_scenario, &hooks, &tool_policy_table).unwrap());
+    }
+
+    /// No hook, no tool policy, and no stubbed response at all is the one
+    /// scenario `replay_approval_scenario` can't resolve offline.
+    #[tokio::test]
+    async fn replay_approval_scenario_errors_without_a_stubbed_response() {
+        let scenario = approval_fixture_scenario("curl https://example.com", None);
+        let hooks = Vec::new();
+        let tool_policy_table = std::collections::HashMap::new();
+
+        assert!(replay_approval_scenario(&scenario, &hooks, &tool_policy_table).is_err());
+    }
+
+    #[tokio::test]
+    async fn parse_approval_response_reads_approve_and_deny() {
+        let (approved, reasoning) = parse_approval_response("APPROVE - this is routine");
+        assert!(approved);
+        assert_eq!(reasoning, "this is routine");
+
+        let (approved, reasoning) = parse_approval_response("DENY - too risky");
+        assert!(!approved);
+        assert_eq!(reasoning, "too risky");
+
+        let (approved, _) = parse_approval_response("uh, maybe?");
+        assert!(!approved, "an unclear response auto-denies for safety");
+    }
+
+    /// A hook authored only for exec requests (e.g. `command_contains` set,
+    /// no `changed_file_pattern`) must not also act as a universal catch-all
+    /// for patch requests.
+    #[test]
+    fn exec_only_hook_does_not_match_patch_requests() {
+        let hook = ApprovalHookConfig {
+            name: "allow curl".to_string(),
+            action: "allow".to_string(),
+            command_contains: Some("curl".to_string()),
+            cwd_prefix: None,
+            changed_file_pattern: None,
+        };
+
+        let mut changes = std::collections::HashMap::new();
+        changes.insert(
+            std::path::PathBuf::from("src/main.rs"),
+            codex_core::protocol::FileChange::Add {
+                content: "fn main() {}".to_string(),
+            },
+        );
+
+        assert!(!patch_approval_hook_matches(&hook, &changes));
+    }
+
+    /// Symmetrically, a hook authored only for patch requests (e.g.
+    /// `changed_file_pattern` set, no exec fields) must not also act as a
+    /// universal catch-all for exec requests.
+    #[test]
+    fn patch_only_hook_does_not_match_exec_requests() {
+        let hook = ApprovalHookConfig {
+            name: "allow src changes".to_string(),
+            action: "allow".to_string(),
+            command_contains: None,
+            cwd_prefix: None,
+            changed_file_pattern: Some("src/*".to_string()),
+        };
+
+        let command = vec!["curl".to_string(), "https://example.com".to_string()];
+        let cwd = std::path::PathBuf::from(".");
+
+        assert!(!exec_approval_hook_matches(&hook, &command, &cwd));
+    }
+
+    /// A hook with none of the matcher fields set is still a legitimate
+    /// catch-all for both request kinds (e.g. a trailing default-deny entry).
+    #[test]
+    fn empty_hook_is_still_a_universal_catch_all() {
+        let hook = ApprovalHookConfig {
+            name: "default deny".to_string(),
+            action: "deny".to_string(),
+            command_contains: None,
+            cwd_prefix: None,
+            changed_file_pattern: None,
+        };
+
+        let command = vec!["rm".to_string(), "-rf".to_string(), "/".to_string()];
+        let cwd = std::path::PathBuf::from(".");
+        assert!(exec_approval_hook_matches(&hook, &command, &cwd));
+
+        let mut changes = std::collections::HashMap::new();
+        changes.insert(
+            std::path::PathBuf::from("src/main.rs"),
+            codex_core::protocol::FileChange::Add {
+                content: "fn main() {}".to_string(),
+            },
+        );
+        assert!(patch_approval_hook_matches(&hook, &changes));
+    }
+}